@@ -1,21 +1,30 @@
 use std::env;
+use std::process::ExitCode;
 
-fn main() {
+fn main() -> ExitCode {
     let mut cwd_fix = env::current_dir().unwrap().join(file!());
     cwd_fix.pop();
     cwd_fix.push("pkg_main");
 
     govld::run::do_run(
         cwd_fix.to_str().unwrap(),
-        govld::run::Args {
-            dir: "vendor".to_string(),
-            patch_manifest_files: vec![
-                "../patch.yaml".to_string(),
-                "../patch_optional.yaml".to_string(),
-                "../patch_optional_but_exists.yaml".to_string(),
-                "../patch_receiver_check.yaml".to_string(),
-                "../patch_overwrite.yaml".to_string(),
-            ],
+        govld::run::Cli {
+            command: govld::run::Command::Apply(govld::run::ApplyArgs {
+                args: govld::run::Args {
+                    dir: "vendor".to_string(),
+                    modules_dir: ".govld_modules".to_string(),
+                    patch_manifest_files: vec![
+                        "../patch.yaml".to_string(),
+                        "../patch_optional.yaml".to_string(),
+                        "../patch_optional_but_exists.yaml".to_string(),
+                        "../patch_receiver_check.yaml".to_string(),
+                        "../patch_overwrite.yaml".to_string(),
+                    ],
+                },
+                dry_run: false,
+                state_dir: ".govld".to_string(),
+                overlay: None,
+            }),
         },
-    );
+    )
 }
@@ -0,0 +1,94 @@
+use std::path::PathBuf;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("error parsing manifest file: {source} at {}", path.display())]
+    ManifestParse {
+        path: PathBuf,
+        source: serde_yaml::Error,
+    },
+
+    // the file targeted by a manifest does not exist
+    #[error("error loading file: {0}")]
+    TargetNotFound(String),
+
+    // the patch code does not contain the declaration its pattern is looking for
+    #[error("error finding {pattern} in patch: {code}")]
+    PatternNotFound { pattern: String, code: String },
+
+    #[error("unknown pattern: {0}")]
+    UnknownPattern(String),
+
+    #[error("error accessing {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    // a file glob matched fewer files than the manifest requires
+    #[error("{file} matched {matches} file(s), expected at least {min_matches}")]
+    TooFewMatches {
+        file: String,
+        matches: usize,
+        min_matches: usize,
+    },
+
+    // the declaration an in-place patch edits does not exist in the source
+    #[error("error finding {pattern} {name} in source")]
+    SymbolNotFound { pattern: String, name: String },
+
+    #[error("patch type {patch_type} is not supported by {pattern}")]
+    UnsupportedPatchType { pattern: String, patch_type: String },
+
+    // the module a manifest is restricted to is required by neither go.mod nor vendor/modules.txt
+    #[error("module {0} is not required by go.mod or vendor/modules.txt")]
+    ModuleNotFound(String),
+
+    // the version of a module is out of the range a manifest requires
+    #[error("{module} {version} does not satisfy {required}")]
+    VersionMismatch {
+        module: String,
+        version: String,
+        required: String,
+    },
+
+    // the same path is imported under two different aliases
+    #[error("error importing {path} in {file}: conflicting aliases {aliases:?}")]
+    ImportConflict {
+        file: String,
+        path: String,
+        aliases: [String; 2],
+    },
+
+    // the source code could not be understood, e.g. it has no package clause
+    #[error("error parsing {file}: {reason}")]
+    Parse { file: String, reason: String },
+}
+
+impl Error {
+    pub(crate) fn io(path: impl Into<PathBuf>) -> impl FnOnce(std::io::Error) -> Self {
+        let path = path.into();
+        move |source| Self::Io { path, source }
+    }
+
+    /// exit_code maps every error to a distinct process exit code for the CLI;
+    /// 1 is left to `check` and 2 to usage errors
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::ManifestParse { .. } => 3,
+            Self::TargetNotFound(_) => 4,
+            Self::PatternNotFound { .. } => 5,
+            Self::UnknownPattern(_) => 6,
+            Self::Io { .. } => 7,
+            Self::Parse { .. } => 8,
+            Self::ImportConflict { .. } => 9,
+            Self::SymbolNotFound { .. } => 10,
+            Self::UnsupportedPatchType { .. } => 11,
+            Self::TooFewMatches { .. } => 12,
+            Self::ModuleNotFound(_) => 13,
+            Self::VersionMismatch { .. } => 14,
+        }
+    }
+}
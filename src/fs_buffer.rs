@@ -1,23 +1,40 @@
-use std::collections::HashMap;
-use std::path::Path;
+use crate::modules::within;
+use crate::{Error, Result};
+use similar::TextDiff;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 
 #[derive(Debug)]
 pub struct FsBuffer<'path> {
     path_prefix: &'path Path,
+
+    // dirs maps import paths to the directory their files are read from instead of
+    // path_prefix, e.g. a module in the module cache; the longest path wins
+    dirs: Vec<(String, PathBuf)>,
     inner: HashMap<String, String>,
+
+    // original keeps the content as it was first loaded from disk,
+    // so that the patched buffer can be diffed against it.
+    original: HashMap<String, String>,
 }
 
 impl<'path> FsBuffer<'path> {
     pub fn new(path_prefix: &'path Path) -> Self {
         Self {
             path_prefix,
+            dirs: Vec::new(),
             inner: HashMap::new(),
+            original: HashMap::new(),
         }
     }
 
-    pub fn load(&mut self, file: String) -> String {
+    pub fn with_dirs(self, dirs: Vec<(String, PathBuf)>) -> Self {
+        Self { dirs, ..self }
+    }
+
+    pub fn load(&mut self, file: String) -> Result<String> {
         match self.inner.get(&file) {
-            Some(v) => v.to_owned(),
+            Some(v) => Ok(v.to_owned()),
             None => self.load_from_file(file.clone()),
         }
     }
@@ -30,17 +47,18 @@ impl<'path> FsBuffer<'path> {
     }
 
     // try loading from file; try joining from path prefix
-    pub fn load_from_file(&mut self, path: String) -> String {
+    pub fn load_from_file(&mut self, path: String) -> Result<String> {
         let prefix_path = self.join_path(&path);
-        let content = std::fs::read_to_string(&prefix_path)
-            .unwrap_or_else(|_| panic!("error opening file: {}", &prefix_path));
+        let content = std::fs::read_to_string(&prefix_path).map_err(Error::io(prefix_path))?;
+        self.original.insert(path.clone(), content.clone());
         self.inner.insert(path, content.clone());
-        content
+        Ok(content)
     }
 
     pub fn try_load_from_file(&mut self, path: String) -> Option<String> {
         let prefix_path = self.join_path(&path);
         let content = std::fs::read_to_string(prefix_path).ok()?;
+        self.original.insert(path.clone(), content.clone());
         self.inner.insert(path, content.clone());
         Some(content)
     }
@@ -49,57 +67,386 @@ impl<'path> FsBuffer<'path> {
         self.inner.insert(path.to_owned(), c.to_owned());
     }
 
-    pub fn append_patch(&mut self, path: &str, patch: &str) {
+    // append_patch appends a block holding the patch, marked with the id of the manifest
+    // it comes from, so that later runs of the manifest can tell its blocks apart
+    pub fn append_patch(&mut self, path: &str, id: &str, patch: &str) -> Result<()> {
         match self.inner.get_mut(path) {
             Some(v) => {
-                append(v, patch);
-            }
-            None => {
-                panic!("error patching file that was never loaded: {}", &path)
+                append(v, id, patch);
+                Ok(())
             }
+            // patching a file that was never loaded
+            None => Err(Error::TargetNotFound(path.to_owned())),
         }
     }
 
-    pub fn apply_patch_at(&mut self, path: &str, patch: &str, safe_range: &std::ops::Range<usize>) {
+    pub fn apply_patch_at(
+        &mut self,
+        path: &str,
+        id: &str,
+        patch: &str,
+        safe_range: &std::ops::Range<usize>,
+    ) -> Result<()> {
         match self.inner.get_mut(path) {
             Some(v) => {
-                prepend(safe_range, v, patch);
+                prepend(safe_range, v, id, patch);
+                Ok(())
             }
-            None => {
-                panic!("error patching file that was never loaded: {}", &path)
+            // patching a file that was never loaded
+            None => Err(Error::TargetNotFound(path.to_owned())),
+        }
+    }
+
+    // expand resolves file patterns into the paths they match, relative to the path prefix.
+    // globs are matched against the disk; plain paths are kept if they exist.
+    pub fn expand(&self, patterns: &[&str]) -> Vec<String> {
+        let mut paths = Vec::new();
+        for pattern in patterns {
+            let matched = match pattern.contains(['*', '?', '[']) {
+                true => self.glob(pattern),
+                false => {
+                    let exists = self.inner.contains_key(*pattern)
+                        || Path::new(&self.join_path(pattern)).is_file();
+                    match exists {
+                        true => vec![pattern.to_string()],
+                        false => Vec::new(),
+                    }
+                }
+            };
+
+            for path in matched {
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
             }
         }
+        paths
     }
 
+    // glob matches a pattern in path_prefix, and in each of dirs whose path matches the
+    // leading components of the pattern; a file is kept from the directory it is read from.
+    fn glob(&self, pattern: &str) -> Vec<String> {
+        let components = pattern.split('/').collect::<Vec<_>>();
+        let roots = std::iter::once((String::new(), self.path_prefix.to_owned()))
+            .chain(self.dirs.iter().cloned());
+
+        let mut matched = Vec::new();
+        for (prefix, dir) in roots {
+            let rest = match prefix.is_empty() {
+                true => pattern.to_owned(),
+                false => {
+                    let leading = prefix.split('/').collect::<Vec<_>>();
+                    let reached = leading.len() < components.len()
+                        && leading.iter().zip(&components).all(|(name, component)| {
+                            glob::Pattern::new(component).map_or(false, |p| p.matches(name))
+                        });
+                    if !reached {
+                        continue;
+                    }
+                    components[leading.len()..].join("/")
+                }
+            };
+
+            let files = glob::glob(dir.join(rest).to_str().unwrap_or_default())
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter(|path| path.is_file())
+                .filter_map(|path| {
+                    let path = path.strip_prefix(&dir).ok()?.to_str()?;
+                    match prefix.is_empty() {
+                        true => Some(path.to_owned()),
+                        false => Some(format!("{}/{}", prefix, path)),
+                    }
+                })
+                .filter(|file| self.dir(file).map_or("", |(prefix, _)| prefix) == prefix);
+            matched.extend(files);
+        }
+        matched
+    }
+
+    // join_path is where a file is read from and written to
     fn join_path(&self, file: &str) -> String {
-        self.path_prefix.join(file).to_str().unwrap().to_string()
+        let path = match self.dir(file) {
+            Some((prefix, dir)) => match file[prefix.len()..].trim_start_matches('/') {
+                "" => dir,
+                rest => dir.join(rest),
+            },
+            None => self.path_prefix.join(file),
+        };
+        path.to_str().unwrap().to_string()
+    }
+
+    fn dir(&self, file: &str) -> Option<(&str, PathBuf)> {
+        self.dirs
+            .iter()
+            .filter(|(prefix, _)| within(file, prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(prefix, dir)| (prefix.as_str(), dir.to_owned()))
+    }
+
+    // original returns the content of a file as it was loaded from disk
+    pub fn original(&self, path: &str) -> Option<&str> {
+        self.original.get(path).map(String::as_str)
     }
 
-    pub fn flush(&self) {
+    // patched returns the content of a file as it is in the buffer
+    pub fn patched(&self, path: &str) -> Option<&str> {
+        self.inner.get(path).map(String::as_str)
+    }
+
+    // modified lists every file whose buffer differs from disk, sorted by path
+    pub fn modified(&self) -> Vec<&str> {
+        let mut paths = self
+            .inner
+            .iter()
+            .filter(|(path, content)| self.original.get(*path) != Some(*content))
+            .map(|(path, _)| path.as_str())
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+    }
+
+    // diff renders a unified diff for every modified file
+    pub fn diff(&self) -> String {
+        self.modified()
+            .into_iter()
+            .map(|path| {
+                let original = self.original.get(path).map(String::as_str).unwrap_or("");
+
+                TextDiff::from_lines(original, self.inner[path].as_str())
+                    .unified_diff()
+                    .header(&format!("a/{}", path), &format!("b/{}", path))
+                    .to_string()
+            })
+            .collect()
+    }
+
+    pub fn flush(&self) -> Result<()> {
         for (path, content) in &self.inner {
             let fspath = self.join_path(path);
-            std::fs::write(fspath, content)
-                .unwrap_or_else(|_| panic!("error writing file: {}", &path));
+            std::fs::write(&fspath, content).map_err(Error::io(fspath))?;
+        }
+
+        Ok(())
+    }
+
+    // flush_overlay writes every modified file into overlay_dir instead of in place, along with
+    // the overlay.json `go build -overlay` takes to replace the file it was read from with it.
+    // returns the path of overlay.json.
+    pub fn flush_overlay(&self, overlay_dir: &Path) -> Result<PathBuf> {
+        let mut replace = BTreeMap::new();
+        for path in self.modified() {
+            let patched = overlay_dir.join(path);
+            if let Some(parent) = patched.parent() {
+                std::fs::create_dir_all(parent).map_err(Error::io(parent))?;
+            }
+            std::fs::write(&patched, &self.inner[path]).map_err(Error::io(&patched))?;
+
+            replace.insert(self.join_path(path), patched);
         }
+
+        let overlay = serde_json::json!({ "Replace": replace });
+        let json_path = overlay_dir.join("overlay.json");
+        std::fs::create_dir_all(overlay_dir).map_err(Error::io(overlay_dir))?;
+        std::fs::write(&json_path, format!("{:#}\n", overlay)).map_err(Error::io(&json_path))?;
+        Ok(json_path)
     }
 }
 
-fn append(code: &mut String, patch: &str) {
-    *code += "\n";
-    *code += "// Patched by govld. DO NOT EDIT\n";
-    *code += patch;
+pub(crate) const BEGIN_MARKER: &str = "// govld:begin ";
+const END_MARKER: &str = "// govld:end ";
+
+fn append(code: &mut String, id: &str, patch: &str) {
+    let block = block(id, patch);
+
+    // the very same block was already appended; nothing to do
+    if code.contains(&block) {
+        return;
+    }
+
+    // blocks are kept in order of id, so that the result is the same whichever manifests
+    // are run together and whichever are run on their own
+    let begin = format!("\n{BEGIN_MARKER}");
+    let next = code.match_indices(&begin).map(|(i, _)| i).find(|&i| {
+        let header = &code[i + begin.len()..];
+        header
+            .split([' ', '\n'])
+            .next()
+            .map_or(false, |other| other > id)
+    });
+    match next {
+        Some(i) => code.insert_str(i, &block),
+        None => *code += &block,
+    }
 }
 
-fn prepend(safe_range: &std::ops::Range<usize>, code: &mut String, patch: &str) {
-    let end = safe_range.end;
-    let patching = format!(
-        r#"
+fn prepend(safe_range: &std::ops::Range<usize>, code: &mut String, id: &str, patch: &str) {
+    let block = block(id, patch);
 
+    if code.contains(&block) {
+        return;
+    }
+
+    code.insert_str(safe_range.end, &block);
+}
+
+// block wraps a patch with begin/end markers carrying the id of its manifest and the hash
+// of its content, so that later runs can recognise (and strip) govld's own output.
+fn block(id: &str, patch: &str) -> String {
+    let hash = hash(patch);
+    let patch = patch.trim_end_matches('\n');
+
+    format!(
+        r#"
+{BEGIN_MARKER}{id} {hash}
 // Patched by govld. DO NOT EDIT
 {patch}
-
+{END_MARKER}{id} {hash}
 "#
-    );
+    )
+}
+
+// hash is a 64-bit FNV-1a; stable across platforms and releases, unlike DefaultHasher
+pub(crate) fn hash(content: &str) -> String {
+    let hash = content.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+
+    format!("{:016x}", hash)
+}
+
+/// strip_patched removes every block previously emitted by govld for the manifest of the
+/// given id, restoring the code to the state it was in right before the blocks were added.
+/// blocks of other manifests are left alone.
+pub fn strip_patched(code: &str, id: &str) -> String {
+    let mut next = code.to_owned();
+    let mut from = 0;
+
+    while let Some(begin) = next[from..].find(&format!("\n{BEGIN_MARKER}")) {
+        let begin = from + begin;
+        let header_start = begin + 1 + BEGIN_MARKER.len();
+        let header_end = next[header_start..]
+            .find('\n')
+            .map_or(next.len(), |i| header_start + i);
+        let header = &next[header_start..header_end];
+        let end_marker = format!("\n{END_MARKER}{}", header);
+
+        // another manifest's block, or an unterminated one; leave it alone rather than guessing
+        let own = header.split(' ').next() == Some(id);
+        let Some(end) = next[header_end..].find(&end_marker).filter(|_| own) else {
+            from = header_end;
+            continue;
+        };
+        let end = header_end + end + end_marker.len();
+        let end = if next[end..].starts_with('\n') {
+            end + 1
+        } else {
+            end
+        };
+
+        next.replace_range(begin..end, "");
+        from = begin;
+    }
 
-    code.insert_str(end, patching.as_str());
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{strip_patched, FsBuffer};
+    use std::path::Path;
+
+    #[test]
+    fn test_diff() {
+        let mut fsb = FsBuffer::new(Path::new("src/patch"));
+        let code = fsb.load("test_parser.go".to_owned()).unwrap();
+        assert_eq!(fsb.diff(), "");
+
+        fsb.update("test_parser.go", &code.replace("Foo", "Bar"));
+
+        let expected = r#"--- a/test_parser.go
++++ b/test_parser.go
+@@ -1,8 +1,8 @@
+ package main
+ 
+ func internal() {
+-	println("Hello, Foo!")
++	println("Hello, Bar!")
+ }
+ func internal2() {
+-	println("Hello, Foo!")
++	println("Hello, Bar!")
+ }
+"#;
+        assert_eq!(fsb.diff(), expected);
+    }
+
+    #[test]
+    fn test_strip_patched() {
+        let mut fsb = FsBuffer::new(Path::new("src/patch"));
+        let code = fsb.load("test_parser.go".to_owned()).unwrap();
+
+        fsb.apply_patch_at("test_parser.go", "a", "import (\n\t\"fmt\"\n)", &(0..12))
+            .unwrap();
+        fsb.append_patch("test_parser.go", "a", "func patched() {}\n")
+            .unwrap();
+        fsb.append_patch("test_parser.go", "a", "func patched() {}\n")
+            .unwrap();
+        let patched = fsb.load("test_parser.go".to_owned()).unwrap();
+        assert_eq!(patched.matches("func patched() {}").count(), 1);
+
+        // only the blocks of the manifest given are stripped
+        fsb.append_patch("test_parser.go", "b", "func other() {}\n")
+            .unwrap();
+        let patched = fsb.load("test_parser.go".to_owned()).unwrap();
+        let stripped = strip_patched(&patched, "a");
+        assert!(!stripped.contains("func patched() {}"));
+        assert!(stripped.contains("func other() {}"));
+        assert_eq!(strip_patched(&stripped, "b"), code);
+    }
+
+    #[test]
+    fn test_expand() {
+        let fsb = FsBuffer::new(Path::new("src"));
+        let paths = fsb.expand(&["patch/*.go", "patch/test_parser.go", "patch/missing.go"]);
+        assert_eq!(paths, vec!["patch/test_parser.go"]);
+        assert_eq!(
+            fsb.expand(&["patch/*.rs", "error.rs"]),
+            vec!["patch/parser.rs", "error.rs"]
+        );
+        assert!(fsb.expand(&["*/missing_*.go"]).is_empty());
+    }
+
+    #[test]
+    fn test_flush_overlay() {
+        let dirs = vec![("pkg".to_owned(), Path::new("src/patch").to_owned())];
+        let mut fsb = FsBuffer::new(Path::new("vendor")).with_dirs(dirs);
+        assert_eq!(fsb.expand(&["pkg/*.go"]), vec!["pkg/test_parser.go"]);
+        let code = fsb.load("pkg/test_parser.go".to_owned()).unwrap();
+        fsb.update("pkg/test_parser.go", &code.replace("Foo", "Bar"));
+
+        let overlay_dir =
+            std::env::temp_dir().join(format!("govld-overlay-{}", std::process::id()));
+        let json_path = fsb.flush_overlay(&overlay_dir).unwrap();
+
+        let patched = overlay_dir.join("pkg/test_parser.go");
+        assert_eq!(
+            std::fs::read_to_string(&patched).unwrap(),
+            code.replace("Foo", "Bar")
+        );
+        let overlay: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(json_path).unwrap()).unwrap();
+        assert_eq!(
+            overlay,
+            serde_json::json!({ "Replace": { "src/patch/test_parser.go": patched } })
+        );
+
+        // the file in place is left untouched
+        assert_eq!(
+            std::fs::read_to_string("src/patch/test_parser.go").unwrap(),
+            code
+        );
+        std::fs::remove_dir_all(overlay_dir).unwrap();
+    }
 }
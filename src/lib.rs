@@ -1,22 +1,30 @@
+mod error;
 pub mod fs_buffer;
 pub mod manifest;
+pub mod modules;
 mod patch;
 mod patterns;
 pub mod run;
+pub mod state;
 
 use log::{info, warn};
 
-use crate::manifest::Manifest;
-use crate::patterns::try_run;
+pub use crate::error::{Error, Result};
+use crate::fs_buffer::strip_patched;
+use crate::manifest::{Manifest, ManifestImport, Patch, PatchType};
+use crate::patterns::{resolve_original, split_by_pattern, try_ident, try_run, DEFAULT_RENAME_TO};
 use patch::parser::Parser;
 
 #[derive(Debug)]
-pub struct Result {
+pub struct PatchResult {
     pub module_name: String,
     pub code: String,
-    pub imports: Vec<String>,
+    pub imports: Vec<ManifestImport>,
     pub patches: Vec<String>,
 
+    /// symbols are the identifiers of the declarations being patched.
+    pub symbols: Vec<String>,
+
     /// safe_range is the range of the code that is safe to patch.
     /// usually right after the module declaration.
     pub safe_range: std::ops::Range<usize>,
@@ -25,77 +33,116 @@ pub struct Result {
 /// try_patch
 /// code: original source code
 /// manifest: patch manifest
-pub fn try_patch(code: String, manifest: &Manifest) -> Result {
+pub fn try_patch(code: String, manifest: &Manifest) -> Result<PatchResult> {
+    // drop whatever a previous run of this manifest has added, so that patching is idempotent;
+    // the blocks are re-added by FsBuffer once all patches are collected.
+    let code = strip_patched(&code, &manifest.id());
+
     // patches is the buffer for the incremental patches.
     // it collects all the patches that will be applied to the code.
     let patches: Vec<String> = Vec::new();
 
     // imports is the buffer for the additional imports.
-    // it collects all the imports that will be appended to the code.
-    // further check for duplicate symbols is needed.
-    let imports: Vec<String> = Vec::new();
+    // it collects all the imports that will be merged into the code;
+    // duplicates are dropped once merged.
+    let imports: Vec<ManifestImport> = Vec::new();
+
+    // symbols collects the identifiers of every patched declaration.
+    let symbols: Vec<String> = Vec::new();
 
     // find out module name
     let package_parser = Parser::<patterns::module_decl::ModuleDeclPattern>::new(code.as_str());
+    let missing_module = || Error::Parse {
+        file: manifest.file.to_string(),
+        reason: "file does not contain any module declaration".to_owned(),
+    };
     let module = package_parser
         .find_first_match()
-        .unwrap_or_else(|| panic!("error finding module declaration"));
+        .ok_or_else(missing_module)?;
     let module_name = module.name;
 
     // find the first safe-point to patch; usually right after the module declaration
-    let safe_range = package_parser
-        .find_next_line()
-        .expect("file does not contain any module declaration");
+    let safe_range = package_parser.find_next_line().ok_or_else(missing_module)?;
 
     info!("patching file: {}", &manifest.file);
     info!("package found: {}", &module_name);
 
     // for each patch, find the target and patch it
-    let (next_code, next_patches, next_imports) = manifest.patch.iter().fold(
-        (code, patches, imports),
-        |(code, mut patches, mut imports), manifest_patch| {
-            let run_result = try_run(
-                manifest_patch.pattern.as_str(),
-                code.to_owned(),
-                manifest_patch.code.to_owned(),
-                manifest_patch.patch_type.as_ref(),
-            );
-
-            // run may have returned None if no matching pattern is found
-            // in this case we just append the patch to the end of the file
-            let next_code = match run_result {
-                Some(next_code) => next_code,
-                None => {
-                    warn!(
-                        "no matching pattern found for patch: {}; appending",
-                        manifest_patch.code
-                    );
-                    code
-                }
-            };
+    let (next_code, next_patches, next_imports, next_symbols) = manifest.patch.iter().try_fold(
+        (code, patches, imports, symbols),
+        |(code, mut patches, mut imports, mut symbols), manifest_patch| {
+            let rename_to = manifest_patch
+                .rename_to
+                .as_deref()
+                .or(manifest.rename_to.as_deref())
+                .unwrap_or(DEFAULT_RENAME_TO);
 
-            let import_string = match &manifest_patch.imports {
-                Some(imports) => imports
-                    .iter()
-                    .map(|imp| [imp.alias.to_owned(), format!("\"{}\"", imp.path)].join(" "))
-                    .collect::<Vec<String>>()
-                    .join("\n"),
-                None => String::new(),
+            // the pattern is detected from the code when omitted;
+            // each pattern detected runs over its own declarations only
+            let parts = match &manifest_patch.pattern {
+                Some(pattern) => vec![(pattern.to_owned(), manifest_patch.code.to_owned())],
+                None => split_by_pattern(&manifest_patch.code)
+                    .into_iter()
+                    .map(|(pattern, part)| (pattern.to_owned(), part))
+                    .collect(),
             };
+            if parts.is_empty() {
+                return Err(Error::PatternNotFound {
+                    pattern: "any declaration".to_owned(),
+                    code: manifest_patch.code.to_owned(),
+                });
+            }
+
+            let mut next_code = code;
+            let mut patch_code = manifest_patch.code.to_owned();
+            for (pattern, part) in &parts {
+                let part_patch = Patch {
+                    code: part.to_owned(),
+                    ..manifest_patch.clone()
+                };
+
+                // run may have returned None if no matching pattern is found
+                // in this case we just append the patch to the end of the file
+                next_code = match try_run(pattern, next_code.to_owned(), &part_patch, rename_to)? {
+                    Some(next_code) => next_code,
+                    None => {
+                        warn!("no matching pattern found for patch: {}; appending", part);
+                        next_code
+                    }
+                };
+
+                if manifest_patch.patch_type == Some(PatchType::Wrap) {
+                    patch_code = resolve_original(pattern, &patch_code, rename_to)?;
+                }
+
+                symbols.extend(match &manifest_patch.name {
+                    Some(name) => vec![name.to_owned()],
+                    None => try_ident(pattern, part)?,
+                });
+            }
+
+            imports.extend(manifest_patch.imports.iter().flatten().cloned());
 
-            imports.push(import_string);
-            patches.push(manifest_patch.code.to_owned());
+            // in-place edits are already part of next_code
+            if !manifest_patch
+                .patch_type
+                .as_ref()
+                .map_or(false, |ty| ty.is_in_place())
+            {
+                patches.push(patch_code);
+            }
 
-            (next_code, patches, imports)
+            Ok::<_, Error>((next_code, patches, imports, symbols))
         },
-    );
+    )?;
 
     // return
-    Result {
+    Ok(PatchResult {
         module_name,
         safe_range,
         code: next_code,
         imports: next_imports,
         patches: next_patches,
-    }
+        symbols: next_symbols,
+    })
 }
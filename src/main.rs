@@ -1,7 +1,8 @@
 use clap::Parser;
-use govld::run::Args;
+use govld::run::Cli;
 use std::env;
+use std::process::ExitCode;
 
-fn main() {
-    govld::run::do_run(env::current_dir().unwrap().to_str().unwrap(), Args::parse());
+fn main() -> ExitCode {
+    govld::run::do_run(env::current_dir().unwrap().to_str().unwrap(), Cli::parse())
 }
@@ -2,65 +2,405 @@ use std::path::Path;
 
 use serde::{Deserialize, Deserializer, Serialize};
 
+use crate::Error;
+
 #[derive(Deserialize, PartialEq, Debug, Clone)]
 pub struct Manifest {
-    pub file: String,
+    #[serde(default)]
+    pub file: ManifestFile,
+
+    // package is a directory of Go files to patch, relative to the target directory;
+    // each patch goes to the file declaring what it patches. replaces `file`.
+    pub package: Option<String>,
     pub patch: Vec<Patch>,
 
     // optional signifies that the patch is optional
     pub optional: Option<bool>,
 
+    // min_matches is the least number of files `file` must match;
+    // defaults to 1, or 0 for optional manifests
+    pub min_matches: Option<usize>,
+
+    // remove imports that are no longer referenced once the patches are applied
+    pub prune_imports: Option<bool>,
+
+    // module and version restrict the manifest to a range of versions of a module,
+    // e.g. `>=0.47, <0.50`; module defaults to the module providing the target
+    pub module: Option<String>,
+    pub version: Option<semver::VersionReq>,
+
+    // version_policy is what to do with a module out of range: skip the manifest (default) or fail
+    pub version_policy: Option<VersionPolicy>,
+
+    // rename_to is the template replaced declarations are renamed with, for every patch
+    // that does not set its own; `{name}` is the original name, `{pattern}` the kind of
+    // declaration, e.g. `function_decl`
+    pub rename_to: Option<String>,
+
     // run this AFTER the patch is applied without errors
     pub postprocess: Option<Vec<Manifest>>,
+
+    // source is the path of the manifest file, as given; it identifies the blocks the
+    // manifest and its postprocess manifests add, so that re-runs strip only their own
+    #[serde(skip)]
+    pub source: String,
 }
 
 impl Manifest {
-    pub fn from_path(path: impl AsRef<Path>) -> Self {
-        let file = std::fs::File::open(path).expect("error opening manifest");
-        let manifest: Self = serde_yaml::from_reader(file).expect("error parsing manifest");
+    pub fn from_path(path: impl AsRef<Path>) -> crate::Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path).map_err(Error::io(path))?;
+        let manifest: Self =
+            serde_yaml::from_reader(file).map_err(|source| Error::ManifestParse {
+                path: path.to_owned(),
+                source,
+            })?;
+
+        manifest.validate().map_err(|reason| Error::ManifestParse {
+            path: path.to_owned(),
+            source: serde::de::Error::custom(reason),
+        })?;
+
+        Ok(manifest)
+    }
+
+    // with_source sets the source of the manifest and of its postprocess manifests
+    pub fn with_source(self, source: &str) -> Self {
+        let postprocess = self.postprocess.map(|posts| {
+            posts
+                .into_iter()
+                .map(|post| post.with_source(source))
+                .collect()
+        });
+
+        Self {
+            source: source.to_owned(),
+            postprocess,
+            ..self
+        }
+    }
+
+    // id identifies the blocks added by the manifest
+    pub fn id(&self) -> String {
+        crate::fs_buffer::hash(&self.source)
+    }
+
+    // target describes what the manifest patches, for messages
+    pub fn target(&self) -> String {
+        match &self.package {
+            Some(package) => package.to_owned(),
+            None => self.file.to_string(),
+        }
+    }
+
+    // validate checks what cannot be expressed by the types alone,
+    // e.g. options only some patch types require
+    fn validate(&self) -> Result<(), String> {
+        match (self.file.patterns().is_empty(), &self.package) {
+            (true, None) => return Err("either file or package is required".to_owned()),
+            (false, Some(_)) => return Err("file and package are exclusive".to_owned()),
+            _ => (),
+        }
+
+        if self.version.is_none() && (self.module.is_some() || self.version_policy.is_some()) {
+            return Err("module and version_policy require a version".to_owned());
+        }
+
+        // a glob may span modules, or start matching within the module path itself
+        let glob = self
+            .file
+            .patterns()
+            .iter()
+            .any(|p| p.contains(['*', '?', '[']));
+        if self.version.is_some() && self.module.is_none() && glob {
+            return Err("version on a glob file requires module to be set".to_owned());
+        }
+
+        for pattern in self.file.patterns() {
+            glob::Pattern::new(pattern)
+                .map_err(|e| format!("invalid file pattern `{}`: {}", pattern, e))?;
+        }
+
+        let templates = self.patch.iter().map(|patch| &patch.rename_to);
+        for rename_to in std::iter::once(&self.rename_to).chain(templates).flatten() {
+            validate_rename_to(rename_to)?;
+        }
+
+        for patch in &self.patch {
+            let selector = matches!(
+                patch.patch_type,
+                Some(PatchType::Delete | PatchType::Rename)
+            );
+
+            if patch.patch_type == Some(PatchType::InsertAfter) && patch.anchor.is_none() {
+                return Err(format!(
+                    "{}: insert_after requires an anchor",
+                    patch.pattern.as_deref().unwrap_or("patch")
+                ));
+            }
+            if selector && patch.pattern.is_none() {
+                return Err(format!(
+                    "{} requires a pattern",
+                    patch.patch_type.as_ref().unwrap()
+                ));
+            }
+            if selector && patch.name.is_none() {
+                return Err(format!(
+                    "{}: {} requires a name",
+                    patch.pattern.as_deref().unwrap_or_default(),
+                    patch.patch_type.as_ref().unwrap()
+                ));
+            }
+            if !selector && patch.code.trim().is_empty() {
+                return Err(format!(
+                    "{}: code is required",
+                    patch.pattern.as_deref().unwrap_or("patch")
+                ));
+            }
+        }
+
+        self.postprocess
+            .iter()
+            .flatten()
+            .try_for_each(Manifest::validate)
+    }
+}
+
+// ManifestFile is the file to patch, relative to the target directory;
+// either a single path or a list of them, each of which may be a glob
+#[derive(Deserialize, PartialEq, Debug, Clone)]
+#[serde(untagged)]
+pub enum ManifestFile {
+    Path(String),
+    Paths(Vec<String>),
+}
+
+impl Default for ManifestFile {
+    fn default() -> Self {
+        Self::Paths(Vec::new())
+    }
+}
+
+impl ManifestFile {
+    pub fn patterns(&self) -> Vec<&str> {
+        match self {
+            Self::Path(path) => vec![path.as_str()],
+            Self::Paths(paths) => paths.iter().map(String::as_str).collect(),
+        }
+    }
+}
 
-        manifest
+impl std::fmt::Display for ManifestFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.patterns().join(", "))
     }
 }
 
+#[derive(Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionPolicy {
+    #[default]
+    Skip,
+    Fail,
+}
+
+// validate_rename_to makes sure a rename template yields distinct, valid identifiers
+fn validate_rename_to(rename_to: &str) -> Result<(), String> {
+    if !rename_to.contains("{name}") {
+        return Err(format!("rename_to `{}` must contain {{name}}", rename_to));
+    }
+
+    // the original would keep its own name, clashing with the patch
+    if rename_to == "{name}" {
+        return Err(format!(
+            "rename_to `{}` must rename to another name",
+            rename_to
+        ));
+    }
+
+    let rendered = rename_to.replace("{name}", "x").replace("{pattern}", "x");
+    if !rendered.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return Err(format!(
+            "rename_to `{}` does not render a valid identifier",
+            rename_to
+        ));
+    }
+
+    Ok(())
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub enum PatchType {
     Clone,
     Overwrite,
+
+    // same as clone, but the patch may call the original through `__original__`
+    Wrap,
+
+    // struct field-level edits; the patch code is a struct holding only the fields to edit
+    AddField,
+    RemoveField,
+    ReplaceField,
+
+    // interface method-level edits; the patch code is an interface holding only the methods to edit
+    AddMethod,
+    RemoveMethod,
+    ReplaceMethod,
+
+    // statement-level insertions into the body of a function or method;
+    // the patch code is the function holding only the statements to insert
+    InsertAtStart,
+    InsertBeforeReturn,
+    InsertAfter,
+
+    // selector-only operations on the declaration named by `name` (and `receiver`); no code
+    Delete,
+    Rename,
+}
+
+const PATCH_TYPES: &[(&str, PatchType)] = &[
+    ("clone", PatchType::Clone),
+    ("overwrite", PatchType::Overwrite),
+    ("wrap", PatchType::Wrap),
+    ("add_field", PatchType::AddField),
+    ("remove_field", PatchType::RemoveField),
+    ("replace_field", PatchType::ReplaceField),
+    ("add_method", PatchType::AddMethod),
+    ("remove_method", PatchType::RemoveMethod),
+    ("replace_method", PatchType::ReplaceMethod),
+    ("insert_at_start", PatchType::InsertAtStart),
+    ("insert_before_return", PatchType::InsertBeforeReturn),
+    ("insert_after", PatchType::InsertAfter),
+    ("delete", PatchType::Delete),
+    ("rename", PatchType::Rename),
+];
+
+impl PatchType {
+    // is_in_place tells whether the patch edits its target in place,
+    // rather than appending the patch code to the end of the file
+    pub fn is_in_place(&self) -> bool {
+        !matches!(
+            self,
+            PatchType::Clone | PatchType::Overwrite | PatchType::Wrap
+        )
+    }
+}
+
+impl std::fmt::Display for PatchType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (name, _) = PATCH_TYPES.iter().find(|(_, ty)| ty == self).unwrap();
+        f.write_str(name)
+    }
 }
 
 fn patch_type_from_str<'de, D>(deserializer: D) -> Result<Option<PatchType>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let s: Option<&str> = Deserialize::deserialize(deserializer)?;
+    let s: Option<String> = Deserialize::deserialize(deserializer)?;
     let Some(s) = s else {
         return Ok(None);
     };
 
-    Ok(Some(match s.to_lowercase().as_str() {
-        "clone" => PatchType::Clone,
-        "overwrite" => PatchType::Overwrite,
-        _ => {
-            return Err(serde::de::Error::custom(
-                "invalid patch type, supported: [\"clone\", \"overwrite\"]",
-            ))
-        }
-    }))
+    match PATCH_TYPES
+        .iter()
+        .find(|(name, _)| *name == s.to_lowercase())
+    {
+        Some((_, ty)) => Ok(Some(ty.clone())),
+        None => Err(serde::de::Error::custom(format!(
+            "invalid patch type, supported: {:?}",
+            PATCH_TYPES.iter().map(|(name, _)| name).collect::<Vec<_>>()
+        ))),
+    }
 }
 
-#[derive(Deserialize, PartialEq, Debug, Clone)]
+#[derive(Deserialize, PartialEq, Debug, Clone, Default)]
 pub struct Patch {
-    pub pattern: String,
+    // pattern is detected from the declarations in code when omitted
+    pub pattern: Option<String>,
     #[serde(deserialize_with = "patch_type_from_str")]
     #[serde(default)]
     pub patch_type: Option<PatchType>,
     pub imports: Option<Vec<ManifestImport>>,
+
+    // anchor is the statement to insert after, for insert_after;
+    // the innermost statement containing this text is picked
+    pub anchor: Option<String>,
+
+    // rename_to overrides the manifest's rename_to for this patch
+    pub rename_to: Option<String>,
+
+    // name and receiver select the declaration to delete or rename, in place of code;
+    // receiver is the receiver type of a method, e.g. `*Store` or `Store`
+    pub name: Option<String>,
+    pub receiver: Option<String>,
+
+    #[serde(default)]
     pub code: String,
 }
 
 #[derive(Deserialize, PartialEq, Debug, Clone)]
 pub struct ManifestImport {
-    pub alias: String,
+    // alias is the package name to import under; omit it to use the package's own name.
+    // `_` imports the package for its side effects only, `.` imports it into the file scope.
+    pub alias: Option<String>,
     pub path: String,
 }
+
+impl ManifestImport {
+    pub fn is_blank(&self) -> bool {
+        self.alias.as_deref() == Some("_")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_rename_to, Manifest};
+
+    #[test]
+    fn test_validate_rename_to() {
+        assert!(validate_rename_to("{name}__replaced_by_{pattern}").is_ok());
+        assert!(validate_rename_to("orig{name}").is_ok());
+
+        assert!(validate_rename_to("original").is_err());
+        assert!(validate_rename_to("{name}-old").is_err());
+        assert!(validate_rename_to("{name}").is_err());
+
+        // every patch's own template is validated as well
+        let manifest = |patch_rename_to: &str| -> Manifest {
+            serde_yaml::from_str(&format!(
+                "file: main.go\nrename_to: orig_{{name}}\npatch:\n  - rename_to: \"{}\"\n    code: func say() {{}}\n",
+                patch_rename_to
+            ))
+            .unwrap()
+        };
+        assert!(manifest("{name}_old").validate().is_ok());
+        assert!(manifest("{name}").validate().is_err());
+
+        // ...and overrides the manifest's
+        let code = "package main\n\nfunc say() {}\n".to_owned();
+        let patched = crate::try_patch(code, &manifest("{name}_old")).unwrap();
+        assert!(patched.code.contains("func say_old()"));
+    }
+
+    #[test]
+    fn test_validate_version() {
+        let manifest = |header: &str| -> Manifest {
+            serde_yaml::from_str(&format!(
+                "{}
+patch:
+  - code: func say() {{}}
+",
+                header
+            ))
+            .unwrap()
+        };
+        let file = "version: \">=1.2\"\nfile: github.com/org/pkg_b/module.go";
+        assert!(manifest(file).validate().is_ok());
+
+        let glob = "version: \">=1.2\"\nfile: github.com/org/*/module.go";
+        assert!(manifest(glob).validate().is_err());
+        let glob = format!("module: github.com/org/pkg_b\n{}", glob);
+        assert!(manifest(&glob).validate().is_ok());
+    }
+}
@@ -0,0 +1,416 @@
+use crate::{Error, Result};
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+
+// SOURCE_STAMP records which directory a module was copied from, inside its copy
+const SOURCE_STAMP: &str = ".govld_source";
+
+/// Modules locates the files manifests refer to by import path, e.g.
+/// `github.com/cosmos/cosmos-sdk/x/bank/keeper/keeper.go`.
+///
+/// vendored projects keep them under the vendor directory, laid out by import path already.
+/// otherwise, the modules required by go.mod are copied from the module cache into a
+/// writable directory laid out the same way, as manifests patching in place come to need them;
+/// everything else reads them straight from the module cache.
+#[derive(Debug)]
+pub struct Modules {
+    // root is the directory files are read from and written to
+    root: PathBuf,
+    modules: Vec<Module>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Module {
+    pub path: String,
+    pub version: String,
+
+    // dir holds the files of the module: its vendor, cache or replacement directory
+    pub dir: PathBuf,
+}
+
+impl Modules {
+    /// detect picks the vendor directory if there is one, or the module cache if cwd holds
+    /// a go.mod, copying modules into modules_dir.
+    pub fn detect(cwd: &Path, vendor_dir: &Path, modules_dir: &Path) -> Result<Self> {
+        let go_mod = cwd.join("go.mod");
+        if vendor_dir.is_dir() || !go_mod.is_file() {
+            let modules_txt = vendor_dir.join("modules.txt");
+            let modules = match std::fs::read_to_string(modules_txt) {
+                Ok(text) => parse_modules_txt(&text, vendor_dir),
+                Err(_) => Vec::new(),
+            };
+
+            return Ok(Self {
+                root: vendor_dir.to_owned(),
+                modules,
+            });
+        }
+
+        let cache = mod_cache().ok_or_else(|| Error::Parse {
+            file: go_mod.display().to_string(),
+            reason: "cannot locate the module cache, set GOMODCACHE".to_owned(),
+        })?;
+        let text = std::fs::read_to_string(&go_mod).map_err(Error::io(&go_mod))?;
+        info!("resolving modules from {}", cache.display());
+
+        Ok(Self {
+            root: modules_dir.to_owned(),
+            modules: parse_go_mod(&text, cwd, &cache),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// find returns the module providing an import path, i.e. the module with the
+    /// longest path the import path is in
+    pub fn find(&self, import_path: &str) -> Option<&Module> {
+        self.modules
+            .iter()
+            .filter(|module| within(import_path, &module.path))
+            .max_by_key(|module| module.path.len())
+    }
+
+    /// dirs maps every module to the directory to read its files from without copying
+    /// anything over: its copy in the root directory if it was copied already, patched
+    /// perhaps, or else the directory the go command reads it from
+    pub fn dirs(&self) -> Vec<(String, PathBuf)> {
+        self.modules
+            .iter()
+            .map(|module| {
+                let target = self.root.join(&module.path);
+                let dir = match self.copied(module) {
+                    true => target,
+                    false => module.dir.to_owned(),
+                };
+                (module.path.to_owned(), dir)
+            })
+            .collect()
+    }
+
+    /// materialize makes sure the modules a file pattern may match are in the root
+    /// directory, copying them over from where they live otherwise
+    pub fn materialize(&self, pattern: &str) -> Result<()> {
+        let literal = pattern.split(['*', '?', '[']).next().unwrap_or_default();
+        let is_glob = literal.len() < pattern.len();
+
+        let nested = self
+            .modules
+            .iter()
+            .filter(|module| is_glob && module.path.starts_with(literal));
+        for module in self.find(literal).into_iter().chain(nested) {
+            self.copy(module)?;
+        }
+        Ok(())
+    }
+
+    // copied tells whether the module was copied into the root directory from where it
+    // lives now, according to the stamp left in its copy
+    fn copied(&self, module: &Module) -> bool {
+        let stamp = self.root.join(&module.path).join(SOURCE_STAMP);
+        let source = module.dir.display().to_string();
+        std::fs::read_to_string(stamp).ok().as_deref() == Some(source.as_str())
+    }
+
+    fn copy(&self, module: &Module) -> Result<()> {
+        let target = self.root.join(&module.path);
+        if module.dir == target || self.copied(module) {
+            return Ok(());
+        }
+
+        let source = module.dir.display().to_string();
+        let stamp = target.join(SOURCE_STAMP);
+        if !module.dir.is_dir() {
+            warn!(
+                "{}@{} not found in {}, try running `go mod download`",
+                module.path, module.version, source
+            );
+            return Ok(());
+        }
+
+        // copied from another version before; start over
+        if target.is_dir() {
+            std::fs::remove_dir_all(&target).map_err(Error::io(&target))?;
+        }
+        copy_dir(&module.dir, &target)?;
+        std::fs::write(&stamp, &source).map_err(Error::io(&stamp))?;
+
+        info!(
+            "copied {}@{} into {}; point go.mod at it with `replace {} => {}`",
+            module.path,
+            module.version,
+            target.display(),
+            module.path,
+            target.display()
+        );
+        Ok(())
+    }
+}
+
+/// satisfies tells whether a Go module version, e.g. `v0.47.5`, is in range.
+/// pre-release and pseudo-versions count as the release they precede,
+/// e.g. `v0.50.0-rc.1` as `0.50.0`.
+pub fn satisfies(version: &str, range: &semver::VersionReq) -> bool {
+    let Ok(mut version) = semver::Version::parse(version.trim_start_matches('v')) else {
+        return false;
+    };
+    version.pre = semver::Prerelease::EMPTY;
+    range.matches(&version)
+}
+
+// within tells whether an import path is path itself or below it
+pub(crate) fn within(import_path: &str, path: &str) -> bool {
+    import_path
+        .strip_prefix(path)
+        .map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+// copy_dir copies a directory recursively. files are written anew rather than copied,
+// so that they do not keep the read-only permissions of the module cache.
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    std::fs::create_dir_all(to).map_err(Error::io(to))?;
+
+    for entry in std::fs::read_dir(from).map_err(Error::io(from))? {
+        let path = entry.map_err(Error::io(from))?.path();
+        let target = to.join(path.file_name().unwrap());
+
+        match path.is_dir() {
+            true => copy_dir(&path, &target)?,
+            false => {
+                let content = std::fs::read(&path).map_err(Error::io(&path))?;
+                std::fs::write(&target, content).map_err(Error::io(&target))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// mod_cache is where the go command keeps downloaded modules
+fn mod_cache() -> Option<PathBuf> {
+    if let Some(cache) = std::env::var_os("GOMODCACHE").filter(|v| !v.is_empty()) {
+        return Some(PathBuf::from(cache));
+    }
+
+    let gopath = std::env::var_os("GOPATH")
+        .filter(|v| !v.is_empty())
+        .and_then(|v| std::env::split_paths(&v).next())
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join("go")))?;
+    Some(gopath.join("pkg").join("mod"))
+}
+
+// escape case-encodes a module path or version the way the module cache does,
+// i.e. every upper case letter becomes `!` followed by its lower case
+fn escape(path: &str) -> String {
+    path.chars()
+        .map(|c| match c.is_ascii_uppercase() {
+            true => format!("!{}", c.to_ascii_lowercase()),
+            false => c.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .concat()
+}
+
+fn is_local(path: &str) -> bool {
+    path.starts_with("./") || path.starts_with("../") || Path::new(path).is_absolute()
+}
+
+// parse_modules_txt reads the modules listed by vendor/modules.txt, e.g.
+//   # github.com/cosmos/cosmos-sdk v0.47.5 => github.com/fork/cosmos-sdk v0.47.5-fork
+fn parse_modules_txt(text: &str, vendor_dir: &Path) -> Vec<Module> {
+    text.lines()
+        .filter_map(|line| line.strip_prefix("# "))
+        .filter_map(|line| {
+            let (required, replacement) = match line.split_once(" => ") {
+                Some((required, replacement)) => (required, Some(replacement)),
+                None => (line, None),
+            };
+            let mut required = required.split_whitespace();
+            let path = required.next()?;
+
+            // the version the code comes from; a replacement carries its own, if any
+            let version = replacement
+                .and_then(|r| r.split_whitespace().nth(1))
+                .or(required.next())
+                .unwrap_or_default();
+
+            Some(Module {
+                path: path.to_owned(),
+                version: version.to_owned(),
+                dir: vendor_dir.join(path),
+            })
+        })
+        .collect()
+}
+
+// parse_go_mod reads the modules required by go.mod, locating each of them in the
+// module cache, or wherever its replace directive points to
+fn parse_go_mod(text: &str, go_mod_dir: &Path, cache: &Path) -> Vec<Module> {
+    let mut requires = Vec::new();
+    let mut replaces = Vec::new();
+
+    let mut block = None;
+    for line in text.lines() {
+        let line = line.split("//").next().unwrap_or_default().trim();
+        if line == ")" {
+            block = None;
+            continue;
+        }
+
+        let (directive, args) = match block {
+            Some(directive) => (directive, line),
+            None => line.split_once(' ').unwrap_or((line, "")),
+        };
+        let args = args.trim();
+        if args == "(" {
+            block = Some(directive);
+            continue;
+        }
+
+        let fields = args
+            .split_whitespace()
+            .map(|field| field.trim_matches('"'))
+            .collect::<Vec<_>>();
+        match (directive, fields.iter().position(|field| *field == "=>")) {
+            ("require", _) if fields.len() == 2 => requires.push((fields[0], fields[1])),
+            ("replace", Some(arrow)) if arrow > 0 => {
+                replaces.push((fields[..arrow].to_vec(), fields[arrow + 1..].to_vec()))
+            }
+            _ => (),
+        }
+    }
+
+    requires
+        .into_iter()
+        .map(|(path, version)| {
+            // a replacement of this very version wins over one of every version
+            let replacement = replaces
+                .iter()
+                .filter(|(old, _)| old[0] == path && old.get(1).map_or(true, |v| *v == version))
+                .max_by_key(|(old, _)| old.len())
+                .map(|(_, new)| new);
+
+            let (version, dir) = match replacement.map(Vec::as_slice) {
+                Some([local]) if is_local(local) => (version, go_mod_dir.join(local)),
+                Some([new, new_version]) => (
+                    *new_version,
+                    cache.join(format!("{}@{}", escape(new), escape(new_version))),
+                ),
+                _ => (
+                    version,
+                    cache.join(format!("{}@{}", escape(path), escape(version))),
+                ),
+            };
+
+            Module {
+                path: path.to_owned(),
+                version: version.to_owned(),
+                dir,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, parse_go_mod, parse_modules_txt, satisfies, Module, Modules};
+    use std::path::Path;
+
+    #[test]
+    fn test_parse_go_mod() {
+        let go_mod = r#"module github.com/newmetric/app
+
+go 1.21
+
+require github.com/BurntSushi/toml v1.3.2
+
+require (
+	github.com/cosmos/cosmos-sdk v0.47.5
+	github.com/cosmos/gogoproto v1.4.10 // indirect
+	cosmossdk.io/math v1.1.2
+)
+
+replace (
+	github.com/cosmos/cosmos-sdk => github.com/newmetric/cosmos-sdk v0.47.5-newmetric
+	cosmossdk.io/math v1.1.2 => ../math
+	cosmossdk.io/math v1.0.0 => ../old-math
+)
+"#;
+        let cache = Path::new("/go/pkg/mod");
+        let modules = parse_go_mod(go_mod, Path::new("/app"), cache);
+
+        let module = |path: &str, version: &str, dir: &str| Module {
+            path: path.to_owned(),
+            version: version.to_owned(),
+            dir: Path::new(dir).to_owned(),
+        };
+        assert_eq!(
+            modules,
+            vec![
+                module(
+                    "github.com/BurntSushi/toml",
+                    "v1.3.2",
+                    "/go/pkg/mod/github.com/!burnt!sushi/toml@v1.3.2"
+                ),
+                module(
+                    "github.com/cosmos/cosmos-sdk",
+                    "v0.47.5-newmetric",
+                    "/go/pkg/mod/github.com/newmetric/cosmos-sdk@v0.47.5-newmetric"
+                ),
+                module(
+                    "github.com/cosmos/gogoproto",
+                    "v1.4.10",
+                    "/go/pkg/mod/github.com/cosmos/gogoproto@v1.4.10"
+                ),
+                module("cosmossdk.io/math", "v1.1.2", "/app/../math"),
+            ]
+        );
+        assert_eq!(
+            escape("github.com/Azure/go-ansiterm"),
+            "github.com/!azure/go-ansiterm"
+        );
+    }
+
+    #[test]
+    fn test_find_in_modules_txt() {
+        let modules_txt = r#"# github.com/cosmos/cosmos-sdk v0.47.5 => github.com/newmetric/cosmos-sdk v0.47.5-newmetric
+## explicit; go 1.20
+github.com/cosmos/cosmos-sdk/x/bank/keeper
+# github.com/cosmos/cosmos-sdk/store v0.1.0
+## explicit
+github.com/cosmos/cosmos-sdk/store
+"#;
+        let vendor = Path::new("vendor");
+        let modules = Modules {
+            root: vendor.to_owned(),
+            modules: parse_modules_txt(modules_txt, vendor),
+        };
+
+        let keeper = modules.find("github.com/cosmos/cosmos-sdk/x/bank/keeper/keeper.go");
+        assert_eq!(keeper.unwrap().version, "v0.47.5-newmetric");
+        let store = modules.find("github.com/cosmos/cosmos-sdk/store/store.go");
+        assert_eq!(
+            store.unwrap().dir,
+            vendor.join("github.com/cosmos/cosmos-sdk/store")
+        );
+        assert!(modules
+            .find("github.com/cosmos/cosmos-sdk-fork/x.go")
+            .is_none());
+
+        // vendored modules are in place already
+        modules
+            .materialize("github.com/cosmos/*/keeper.go")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_satisfies() {
+        let range = semver::VersionReq::parse(">=0.47, <0.50").unwrap();
+        assert!(satisfies("v0.47.5", &range));
+        assert!(satisfies("v0.47.6-0.20230911103101-0d5e6bd1c4a8", &range));
+        assert!(satisfies("v0.49.0+incompatible", &range));
+        assert!(!satisfies("v0.50.0-rc.1", &range));
+        assert!(!satisfies("v0.46.15", &range));
+        assert!(!satisfies("", &range));
+    }
+}
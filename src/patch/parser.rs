@@ -10,6 +10,30 @@ pub struct Parser<P> {
     _pattern: PhantomData<P>,
 }
 
+// query collects every match of a query that is not a declaration Pattern, e.g. imports,
+// converting each with from_match
+pub fn query<T>(
+    code: &str,
+    sexp: &str,
+    from_match: impl Fn(&tree_sitter::QueryMatch, &str) -> T,
+) -> Vec<T> {
+    let mut parser = tree_sitter::Parser::new();
+    let language = tree_sitter_go::language();
+    parser
+        .set_language(&language)
+        .expect("error loading Go grammar");
+    let tree = parser.parse(code, None).unwrap();
+
+    let mut cursor = tree_sitter::QueryCursor::new();
+    let query = tree_sitter::Query::new(&language, sexp).expect("query is invalid");
+    cursor
+        .matches(&query, tree.root_node(), |node: tree_sitter::Node| {
+            std::iter::once(&code.as_bytes()[node.byte_range()])
+        })
+        .map(|m| from_match(&m, code))
+        .collect()
+}
+
 impl<P: Pattern> Parser<P> {
     pub fn new(code: &str) -> Self {
         let mut parser = tree_sitter::Parser::new();
@@ -29,6 +53,10 @@ impl<P: Pattern> Parser<P> {
         }
     }
 
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
     // find_first_match finds the first s_exp match in the code
     // useful for searching for a single match for a target pattern
     pub fn find_first_match(&self) -> Option<P> {
@@ -45,6 +73,36 @@ impl<P: Pattern> Parser<P> {
             .next()
     }
 
+    // find_all_matches collects every s_exp match in the code
+    pub fn find_all_matches(&self) -> Vec<P> {
+        let mut cursor = tree_sitter::QueryCursor::new();
+        let query = tree_sitter::Query::new(&self.language, P::sexp()).expect("query is invalid");
+
+        cursor
+            .matches(&query, self.tree.root_node(), |node: tree_sitter::Node| {
+                let cb = self.code.as_bytes();
+                let slice = &cb[node.byte_range()];
+                std::iter::once(slice)
+            })
+            .map(|m| P::from_match(&m, &self.code))
+            .collect()
+    }
+
+    // find_node returns the node of the first match satisfying the predicate,
+    // i.e. the outermost capture of the pattern
+    pub fn find_node(&self, predicate: impl Fn(&P) -> bool) -> Option<tree_sitter::Node> {
+        let mut cursor = tree_sitter::QueryCursor::new();
+        let query = tree_sitter::Query::new(&self.language, P::sexp()).expect("query is invalid");
+
+        cursor
+            .matches(&query, self.tree.root_node(), |node: tree_sitter::Node| {
+                let cb = self.code.as_bytes();
+                let slice = &cb[node.byte_range()];
+                std::iter::once(slice)
+            })
+            .find_map(|m| predicate(&P::from_match(&m, &self.code)).then(|| m.captures[0].node))
+    }
+
     pub fn find_next_line(&self) -> Option<Range<usize>> {
         let mut cursor = tree_sitter::QueryCursor::new();
         let query = tree_sitter::Query::new(&self.language, P::sexp()).expect("query is invalid");
@@ -62,7 +120,11 @@ impl<P: Pattern> Parser<P> {
 
     // find_and_patch replaces original codebuffer if matching pattern is found in the source
     // otherwise append at the bottom
-    pub fn find_and_patch(&self, predicate: impl Fn(&P) -> bool) -> Option<String> {
+    pub fn find_and_patch(
+        &self,
+        predicate: impl Fn(&P) -> bool,
+        rename_to: &str,
+    ) -> Option<String> {
         let mut cursor = tree_sitter::QueryCursor::new();
         let query = tree_sitter::Query::new(&self.language, P::sexp()).expect("query is invalid");
 
@@ -76,7 +138,7 @@ impl<P: Pattern> Parser<P> {
             .find_map(|m| {
                 let patt = P::from_match(&m, &self.code);
 
-                predicate(&patt).then(|| P::append_suffix(&m, &self.code))
+                predicate(&patt).then(|| P::append_suffix(&m, &self.code, rename_to))
             })
     }
 
@@ -102,7 +164,7 @@ impl<P: Pattern> Parser<P> {
 #[cfg(test)]
 mod tests {
     use super::Parser;
-    use crate::patterns::{self, func_decl::FunctionDeclPattern};
+    use crate::patterns::{self, func_decl::FunctionDeclPattern, DEFAULT_RENAME_TO};
 
     #[test]
     fn test_parser() {
@@ -114,6 +176,7 @@ mod tests {
             next,
             Some(FunctionDeclPattern {
                 name: "internal".to_owned(),
+                type_params: String::new(),
                 param_t: "()".to_owned(),
                 return_t: String::new(),
                 fn_itself: "func internal() {\n\tprintln(\"Hello, Foo!\")\n}".to_owned(),
@@ -138,6 +201,7 @@ println("Hello, World!")
             patch_target,
             FunctionDeclPattern {
                 name: "internal".to_owned(),
+                type_params: String::new(),
                 param_t: "()".to_owned(),
                 return_t: String::new(),
                 fn_itself: "func internal() {\nprintln(\"Hello, World!\")\n}".to_owned(),
@@ -145,7 +209,8 @@ println("Hello, World!")
         );
 
         let source_parser = Parser::<patterns::func_decl::FunctionDeclPattern>::new(source);
-        let result = source_parser.find_and_patch(|f| f.name == patch_target.name);
+        let result =
+            source_parser.find_and_patch(|f| f.name == patch_target.name, DEFAULT_RENAME_TO);
 
         let expected = "package main\n\nfunc internal__replaced_by_function_decl() {\n\tprintln(\"Hello, Foo!\")\n}\nfunc internal2() {\n\tprintln(\"Hello, Foo!\")\n}\n";
         assert_eq!(result, Some(expected.to_owned()))
@@ -164,6 +229,7 @@ println("Hello, World!")
             patch_target,
             FunctionDeclPattern {
                 name: "internal".to_owned(),
+                type_params: String::new(),
                 param_t: "()".to_owned(),
                 return_t: String::new(),
                 fn_itself: "func internal() {}".to_owned(),
@@ -176,4 +242,35 @@ println("Hello, World!")
         let expected = "package main\n\n\nfunc internal2() {\n\tprintln(\"Hello, Foo!\")\n}\n";
         assert_eq!(result, Some(expected.to_owned()))
     }
+
+    #[test]
+    fn test_run_multiple_declarations() {
+        let source = include_str!("./test_parser.go");
+        let patch = "func internal() {}\n\nfunc internal3() {}\n\nfunc internal2() {}\n";
+
+        let result = patterns::run::<FunctionDeclPattern>(
+            "function_declaration",
+            source,
+            patch,
+            None,
+            DEFAULT_RENAME_TO,
+        );
+        let expected = source
+            .replace("internal()", "internal__replaced_by_function_decl()")
+            .replace("internal2()", "internal2__replaced_by_function_decl()");
+        assert_eq!(result.unwrap(), Some(expected));
+
+        let result = patterns::run::<FunctionDeclPattern>(
+            "function_declaration",
+            source,
+            "func internal3() {}",
+            None,
+            DEFAULT_RENAME_TO,
+        );
+        assert_eq!(result.unwrap(), None);
+        assert_eq!(
+            patterns::try_ident("function_declaration", patch).unwrap(),
+            vec!["internal", "internal3", "internal2"]
+        );
+    }
 }
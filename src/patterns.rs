@@ -1,46 +1,197 @@
 use crate::patch::parser::Parser;
 
-use crate::manifest::PatchType;
+use crate::manifest::{Patch, PatchType};
+use crate::{Error, Result};
+use log::warn;
 
+pub mod const_decl;
 pub mod func_decl;
+pub mod import_decl;
 pub mod method_decl;
 pub mod module_decl;
 
-mod import_decl;
+mod members;
+mod statements;
+
 mod interface_decl;
 mod struct_decl;
+mod type_decl;
 mod variable_decl;
 
 pub trait Pattern
 where
     Self: Sized,
 {
+    // KIND is the short name of the pattern, substituted for `{pattern}` in rename templates.
+    const KIND: &'static str;
+
     // ident returns whatever human-readable identifier for the pattern.
     fn ident(&self) -> String;
 
+    // renamed returns the name a replaced declaration named name is renamed to,
+    // according to the rename_to template.
+    fn renamed(name: &str, rename_to: &str) -> String {
+        rename_to
+            .replace("{name}", name)
+            .replace("{pattern}", Self::KIND)
+    }
+
+    // receiver returns the base receiver type of a method; None for anything else.
+    fn receiver(&self) -> Option<String> {
+        None
+    }
+
     // sexp returns S_EXP for the pattern.
     fn sexp() -> &'static str;
 
     // hehe
     fn from_match(matched: &tree_sitter::QueryMatch, code: &str) -> Self;
-    fn append_suffix(matched: &tree_sitter::QueryMatch, codebuf: &str) -> String;
+    fn append_suffix(matched: &tree_sitter::QueryMatch, codebuf: &str, rename_to: &str) -> String;
     fn delete(matched: &tree_sitter::QueryMatch, codebuf: &str) -> String;
     fn is_match(&self, other: &Self) -> bool;
 }
 
+// split_by_pattern detects the pattern of every top-level declaration in code,
+// returning the declarations of each pattern found, in order of first appearance
+pub fn split_by_pattern(code: &str) -> Vec<(&'static str, String)> {
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(&tree_sitter_go::language())
+        .expect("error loading Go grammar");
+    let Some(tree) = parser.parse(code, None) else {
+        return Vec::new();
+    };
+
+    let root = tree.root_node();
+    let mut cursor = root.walk();
+    let decls = root
+        .named_children(&mut cursor)
+        .flat_map(|node| -> Vec<(&'static str, String)> {
+            let text = &code[node.byte_range()];
+            match node.kind() {
+                "function_declaration" => vec![("function_declaration", text.to_owned())],
+                "method_declaration" => vec![("method_declaration", text.to_owned())],
+                "var_declaration" => vec![("variable_declaration", text.to_owned())],
+                "const_declaration" => vec![("const_declaration", text.to_owned())],
+                // each spec of a grouped type ( ... ) on its own, as they may differ
+                "type_declaration" => {
+                    let mut cursor = node.walk();
+                    let specs = node.named_children(&mut cursor).collect::<Vec<_>>();
+                    specs
+                        .into_iter()
+                        .filter(|spec| matches!(spec.kind(), "type_spec" | "type_alias"))
+                        .map(|spec| {
+                            let pattern = match spec.child_by_field_name("type").map(|ty| ty.kind())
+                            {
+                                Some("struct_type") if spec.kind() == "type_spec" => {
+                                    "struct_declaration"
+                                }
+                                Some("interface_type") if spec.kind() == "type_spec" => {
+                                    "interface_declaration"
+                                }
+                                _ => "type_declaration",
+                            };
+                            (pattern, format!("type {}", &code[spec.byte_range()]))
+                        })
+                        .collect()
+                }
+                _ => Vec::new(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    decls.into_iter().fold(
+        Vec::new(),
+        |mut split: Vec<(&'static str, String)>, (pattern, text)| {
+            match split.iter_mut().find(|(p, _)| *p == pattern) {
+                Some((_, code)) => *code = format!("{}\n\n{}", code, text),
+                None => split.push((pattern, text)),
+            }
+            split
+        },
+    )
+}
+
+// DEFAULT_RENAME_TO is the template replaced declarations are renamed with,
+// e.g. `say__replaced_by_function_decl`
+pub const DEFAULT_RENAME_TO: &str = "{name}__replaced_by_{pattern}";
+
 pub fn try_run(
     pattern: &str,
     code: String,
-    patch: String,
-    patch_type: Option<&PatchType>,
-) -> Option<String> {
+    manifest_patch: &Patch,
+    rename_to: &str,
+) -> Result<Option<String>> {
+    let patch = &manifest_patch.code;
+    let patch_type = manifest_patch.patch_type.as_ref();
+
+    // selector-only operations apply to any declaration
+    if let Some(PatchType::Delete | PatchType::Rename) = patch_type {
+        macro_rules! select {
+            ($ty: ty) => {
+                select::<$ty>(pattern, &code, manifest_patch, rename_to)
+            };
+        }
+
+        return match pattern {
+            "function_declaration" => select!(func_decl::FunctionDeclPattern),
+            "method_declaration" => select!(method_decl::MethodDeclPattern),
+            "struct_declaration" => select!(struct_decl::StructDeclPattern),
+            "interface_declaration" => select!(interface_decl::InterfaceDeclPattern),
+            "variable_declaration" => select!(variable_decl::VariableDeclPattern),
+            "const_declaration" => select!(const_decl::ConstDeclPattern),
+            "type_declaration" => select!(type_decl::TypeDeclPattern),
+            _ => Err(Error::UnknownPattern(pattern.to_owned())),
+        }
+        .map(Some);
+    }
+
+    // in-place edits are dispatched to the patterns supporting them
+    if let Some(patch_type) = patch_type.filter(|ty| ty.is_in_place()) {
+        let insertion = matches!(
+            patch_type,
+            PatchType::InsertAtStart | PatchType::InsertBeforeReturn | PatchType::InsertAfter
+        );
+
+        return match (pattern, patch_type) {
+            (
+                "struct_declaration",
+                PatchType::AddField | PatchType::RemoveField | PatchType::ReplaceField,
+            ) => struct_decl::edit_fields(&code, patch, patch_type).map(Some),
+            (
+                "interface_declaration",
+                PatchType::AddMethod | PatchType::RemoveMethod | PatchType::ReplaceMethod,
+            ) => interface_decl::edit_methods(&code, patch, patch_type).map(Some),
+            ("function_declaration", _) if insertion => statements::insert_statements::<
+                func_decl::FunctionDeclPattern,
+            >(
+                pattern, &code, manifest_patch
+            )
+            .map(Some),
+            ("method_declaration", _) if insertion => statements::insert_statements::<
+                method_decl::MethodDeclPattern,
+            >(pattern, &code, manifest_patch)
+            .map(Some),
+            _ => Err(Error::UnsupportedPatchType {
+                pattern: pattern.to_owned(),
+                patch_type: patch_type.to_string(),
+            }),
+        };
+    }
+
+    // only functions and methods can be called by their wrapper
+    if patch_type == Some(&PatchType::Wrap)
+        && !matches!(pattern, "function_declaration" | "method_declaration")
+    {
+        return Err(Error::UnsupportedPatchType {
+            pattern: pattern.to_owned(),
+            patch_type: PatchType::Wrap.to_string(),
+        });
+    }
+
     macro_rules! run {
         ($ty: ty) => {
-            run(
-                &Parser::<$ty>::new(&code),
-                &Parser::<$ty>::new(&patch),
-                patch_type,
-            )
+            run::<$ty>(pattern, &code, patch, patch_type, rename_to)
         };
     }
 
@@ -50,30 +201,537 @@ pub fn try_run(
         "struct_declaration" => run!(struct_decl::StructDeclPattern),
         "interface_declaration" => run!(interface_decl::InterfaceDeclPattern),
         "variable_declaration" => run!(variable_decl::VariableDeclPattern),
-        _ => panic!("unknown pattern: {}", pattern),
+        "const_declaration" => run!(const_decl::ConstDeclPattern),
+        "type_declaration" => run!(type_decl::TypeDeclPattern),
+        _ => Err(Error::UnknownPattern(pattern.to_owned())),
+    }
+}
+
+// try_ident returns the identifiers of the declarations found in the patch
+pub fn try_ident(pattern: &str, patch: &str) -> Result<Vec<String>> {
+    macro_rules! ident {
+        ($ty: ty) => {
+            Ok(targets(&Parser::<$ty>::new(patch))
+                .iter()
+                .map(|pat| pat.ident())
+                .collect())
+        };
+    }
+
+    match pattern {
+        "function_declaration" => ident!(func_decl::FunctionDeclPattern),
+        "method_declaration" => ident!(method_decl::MethodDeclPattern),
+        "struct_declaration" => ident!(struct_decl::StructDeclPattern),
+        "interface_declaration" => ident!(interface_decl::InterfaceDeclPattern),
+        "variable_declaration" => ident!(variable_decl::VariableDeclPattern),
+        "const_declaration" => ident!(const_decl::ConstDeclPattern),
+        "type_declaration" => ident!(type_decl::TypeDeclPattern),
+        _ => Err(Error::UnknownPattern(pattern.to_owned())),
+    }
+}
+
+// resolve_original points every `__original__` placeholder in the patch at the name
+// its declaration is renamed to in the source, so that a wrapper can call the original.
+pub fn resolve_original(pattern: &str, patch: &str, rename_to: &str) -> Result<String> {
+    match pattern {
+        "function_declaration" => Ok(resolve::<func_decl::FunctionDeclPattern>(patch, rename_to)),
+        "method_declaration" => Ok(resolve::<method_decl::MethodDeclPattern>(patch, rename_to)),
+        _ => Err(Error::UnsupportedPatchType {
+            pattern: pattern.to_owned(),
+            patch_type: PatchType::Wrap.to_string(),
+        }),
+    }
+}
+
+const ORIGINAL_PLACEHOLDER: &str = "__original__";
+
+fn resolve<P: Pattern>(patch: &str, rename_to: &str) -> String {
+    let parser = Parser::<P>::new(patch);
+
+    // collect the placeholders within each declaration, e.g. `__original__(x)` or `s.__original__(x)`
+    let mut placeholders = Vec::new();
+    for target in targets(&parser) {
+        let Some(decl) = parser.find_node(|pat| pat.is_match(&target)) else {
+            continue;
+        };
+
+        let mut stack = vec![decl];
+        while let Some(node) = stack.pop() {
+            if matches!(node.kind(), "identifier" | "field_identifier")
+                && &patch[node.byte_range()] == ORIGINAL_PLACEHOLDER
+            {
+                placeholders.push((node.byte_range(), P::renamed(&target.ident(), rename_to)));
+            }
+
+            let mut cursor = node.walk();
+            stack.extend(node.named_children(&mut cursor));
+        }
+    }
+
+    placeholders.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    placeholders
+        .into_iter()
+        .fold(patch.to_owned(), |mut next, (range, renamed)| {
+            next.replace_range(range, &renamed);
+            next
+        })
+}
+
+// targets lists every declaration in the patch, once each; a multi-name
+// var or a struct with several fields yields more than one match
+fn targets<P: Pattern>(parser: &Parser<P>) -> Vec<P> {
+    parser
+        .find_all_matches()
+        .into_iter()
+        .fold(Vec::new(), |mut targets: Vec<P>, pat| {
+            if !targets.iter().any(|target| target.is_match(&pat)) {
+                targets.push(pat);
+            }
+            targets
+        })
+}
+
+// select deletes or renames every declaration named by the patch's name and receiver,
+// e.g. all the `init` functions of a file
+fn select<P: Pattern>(
+    pattern: &str,
+    code: &str,
+    manifest_patch: &Patch,
+    rename_to: &str,
+) -> Result<String> {
+    let name = manifest_patch.name.as_deref().unwrap_or_default();
+    let selected = selector::<P>(manifest_patch);
+
+    let mut next = code.to_owned();
+    let mut count = 0;
+    loop {
+        let parser = Parser::<P>::new(&next);
+        let edited = match manifest_patch.patch_type {
+            Some(PatchType::Delete) => parser.find_and_delete(&selected),
+            _ => parser.find_and_patch(&selected, rename_to),
+        };
+        match edited {
+            Some(edited) => next = edited,
+            None => break,
+        }
+        count += 1;
+    }
+
+    if count > 0 {
+        return Ok(next);
+    }
+
+    // renamed or deleted by a previous run already
+    let renamed = P::renamed(name, rename_to);
+    match manifest_patch.patch_type {
+        Some(PatchType::Rename)
+            if Parser::<P>::new(code)
+                .find_node(|pat| pat.ident() == renamed)
+                .is_none() =>
+        {
+            Err(Error::SymbolNotFound {
+                pattern: pattern.to_owned(),
+                name: name.to_owned(),
+            })
+        }
+        _ => {
+            warn!("{}: {} not found; nothing to do", pattern, name);
+            Ok(next)
+        }
+    }
+}
+
+// selector matches the declarations named by the patch's name and receiver
+fn selector<P: Pattern>(manifest_patch: &Patch) -> impl Fn(&P) -> bool + '_ {
+    let name = manifest_patch.name.as_deref().unwrap_or_default();
+    let receiver = manifest_patch.receiver.as_deref().map(|receiver| {
+        let receiver = receiver.trim_start_matches('*');
+        receiver.split('[').next().unwrap_or(receiver).trim()
+    });
+
+    move |pat: &P| {
+        pat.ident() == name && receiver.map_or(true, |r| pat.receiver().as_deref() == Some(r))
+    }
+}
+
+// declares tells whether code declares any of the declarations the patch targets,
+// either as is or renamed by a previous run
+pub fn declares(code: &str, manifest_patch: &Patch, rename_to: &str) -> Result<bool> {
+    let parts = match &manifest_patch.pattern {
+        Some(pattern) => vec![(pattern.as_str(), manifest_patch.code.to_owned())],
+        None => split_by_pattern(&manifest_patch.code),
+    };
+
+    macro_rules! declared {
+        ($ty: ty, $patch: expr) => {
+            declared::<$ty>(code, $patch, rename_to)
+        };
     }
+
+    for (pattern, part) in parts {
+        let patch = &Patch {
+            code: part,
+            ..manifest_patch.clone()
+        };
+        let found = match pattern {
+            "function_declaration" => declared!(func_decl::FunctionDeclPattern, patch),
+            "method_declaration" => declared!(method_decl::MethodDeclPattern, patch),
+            "struct_declaration" => declared!(struct_decl::StructDeclPattern, patch),
+            "interface_declaration" => declared!(interface_decl::InterfaceDeclPattern, patch),
+            "variable_declaration" => declared!(variable_decl::VariableDeclPattern, patch),
+            "const_declaration" => declared!(const_decl::ConstDeclPattern, patch),
+            "type_declaration" => declared!(type_decl::TypeDeclPattern, patch),
+            _ => return Err(Error::UnknownPattern(pattern.to_owned())),
+        };
+        if found {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
 }
 
+fn declared<P: Pattern>(code: &str, manifest_patch: &Patch, rename_to: &str) -> bool {
+    let source = Parser::<P>::new(code);
+
+    if let Some(PatchType::Delete | PatchType::Rename) = manifest_patch.patch_type {
+        let name = manifest_patch.name.as_deref().unwrap_or_default();
+        let renamed = P::renamed(name, rename_to);
+        let selected = selector::<P>(manifest_patch);
+        return source
+            .find_node(|pat| selected(pat) || pat.ident() == renamed)
+            .is_some();
+    }
+
+    targets(&Parser::<P>::new(&manifest_patch.code))
+        .iter()
+        .any(|target| {
+            let renamed = P::renamed(&target.ident(), rename_to);
+            source
+                .find_node(|pat| pat.is_match(target) || pat.ident() == renamed)
+                .is_some()
+        })
+}
+
+// run matches every declaration in the patch against the source, one at a time,
+// renaming or deleting each original found. returns None if none was found.
 pub fn run<P: Pattern>(
-    source_parser: &Parser<P>,
-    target_parser: &Parser<P>,
+    pattern: &str,
+    code: &str,
+    patch: &str,
     patch_type: Option<&PatchType>,
-) -> Option<String> {
-    match patch_type {
-        Some(PatchType::Overwrite) => source_parser.find_and_delete(|pat| {
-            pat.is_match(
-                &target_parser
-                    .find_first_match()
-                    .unwrap_or_else(|| panic!("error finding target pattern")),
-            )
-        }),
-        // default behavior
-        _ => source_parser.find_and_patch(|pat| {
-            pat.is_match(
-                &target_parser
-                    .find_first_match()
-                    .unwrap_or_else(|| panic!("error finding target pattern")),
-            )
-        }),
+    rename_to: &str,
+) -> Result<Option<String>> {
+    let targets = targets(&Parser::<P>::new(patch));
+    if targets.is_empty() {
+        return Err(Error::PatternNotFound {
+            pattern: pattern.to_owned(),
+            code: patch.to_owned(),
+        });
+    }
+
+    targets
+        .iter()
+        .try_fold(None, |next: Option<String>, target| {
+            let source_parser = Parser::<P>::new(next.as_deref().unwrap_or(code));
+
+            let patched = match patch_type {
+                Some(PatchType::Overwrite) => {
+                    source_parser.find_and_delete(|pat| pat.is_match(target))
+                }
+                // the wrapper calls the original, so it has to exist; unless renamed by a previous run
+                Some(PatchType::Wrap) => {
+                    match source_parser.find_and_patch(|pat| pat.is_match(target), rename_to) {
+                        Some(patched) => Some(patched),
+                        None if source_parser
+                            .find_node(|pat| pat.ident() == P::renamed(&target.ident(), rename_to))
+                            .is_some() =>
+                        {
+                            Some(source_parser.code().to_owned())
+                        }
+                        None => {
+                            return Err(Error::SymbolNotFound {
+                                pattern: pattern.to_owned(),
+                                name: target.ident(),
+                            })
+                        }
+                    }
+                }
+                // default behavior
+                _ => source_parser.find_and_patch(|pat| pat.is_match(target), rename_to),
+            };
+
+            Ok(patched.or(next))
+        })
+}
+
+// delete_name deletes a single name declared by a var/const declaration.
+// the whole declaration goes away if that name is all it declares, and so does the
+// spec's line if it declares nothing else. otherwise, or if blank_spec is set, the name
+// is blanked out as `_`, leaving the other names (and iota numbering) intact.
+pub(crate) fn delete_name(
+    decl: tree_sitter::Node,
+    spec: tree_sitter::Node,
+    name: tree_sitter::Node,
+    codebuf: &str,
+    blank_spec: bool,
+) -> String {
+    let count_names = |spec: tree_sitter::Node| {
+        let mut cursor = spec.walk();
+        let count = spec
+            .named_children(&mut cursor)
+            .filter(|child| child.kind() == "identifier")
+            .count();
+        count
+    };
+
+    let mut cursor = decl.walk();
+    let decl_names = decl
+        .named_children(&mut cursor)
+        .filter(|spec| spec.kind().ends_with("_spec"))
+        .map(count_names)
+        .sum::<usize>();
+
+    let mut next = codebuf.to_owned();
+    match (decl_names, count_names(spec)) {
+        (1, _) => next.replace_range(decl.byte_range(), ""),
+        (_, 1) if !blank_spec => next.replace_range(line_range(codebuf, &spec.byte_range()), ""),
+        _ => next.replace_range(name.byte_range(), "_"),
+    }
+    next
+}
+
+// line_range widens a range to the whole line(s) it sits on, if nothing else is on them
+pub(crate) fn line_range(code: &str, range: &std::ops::Range<usize>) -> std::ops::Range<usize> {
+    let before = &code[..range.start];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let start = match before[line_start..].trim().is_empty() {
+        true => line_start,
+        false => range.start,
+    };
+
+    let end = match code[range.end..].starts_with('\n') {
+        true => range.end + 1,
+        false => range.end,
+    };
+
+    start..end
+}
+
+// indent_at returns the whitespace the line pos is on starts with
+pub(crate) fn indent_at(code: &str, pos: usize) -> String {
+    let line_start = code[..pos].rfind('\n').map_or(0, |i| i + 1);
+    code[line_start..pos]
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .collect()
+}
+
+// base_type strips pointers, packages and type arguments from a type,
+// e.g. `Store` for `*Store[K, V]` or `Context` for `sdk.Context`
+pub(crate) fn base_type<'a>(node: tree_sitter::Node, code: &'a str) -> &'a str {
+    match node.kind() {
+        "pointer_type" | "parenthesized_type" => match node.named_child(0) {
+            Some(inner) => base_type(inner, code),
+            None => &code[node.byte_range()],
+        },
+        "generic_type" => match node.child_by_field_name("type") {
+            Some(inner) => base_type(inner, code),
+            None => &code[node.byte_range()],
+        },
+        "qualified_type" => match node.child_by_field_name("name") {
+            Some(inner) => base_type(inner, code),
+            None => &code[node.byte_range()],
+        },
+        _ => &code[node.byte_range()],
+    }
+}
+
+// delete_type_spec removes the type_declaration holding spec, or only the spec's line
+// if the declaration groups several specs, e.g. type ( A struct{}; B interface{} )
+fn delete_type_spec(code: &str, spec: tree_sitter::Node) -> String {
+    let decl = spec.parent().unwrap();
+    let mut cursor = decl.walk();
+    let specs = decl
+        .named_children(&mut cursor)
+        .filter(|child| child.kind() == "type_spec" || child.kind() == "type_alias")
+        .count();
+
+    let mut next = code.to_owned();
+    match specs {
+        1 => next.replace_range(decl.byte_range(), ""),
+        _ => next.replace_range(line_range(code, &spec.byte_range()), ""),
+    }
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        declares, method_decl, resolve_original, split_by_pattern, try_run, DEFAULT_RENAME_TO,
+    };
+    use crate::manifest::{Patch, PatchType};
+    use crate::patch::parser::Parser;
+
+    const SOURCE: &str = r#"package main
+
+type Store[K comparable, V any] struct{ m map[K]V }
+
+func (s *Store[K, V]) Get(k K) V { return s.m[k] }
+
+func (s Store[K, V]) Len() int { return len(s.m) }
+"#;
+
+    #[test]
+    fn test_wrap() {
+        let patch = "func (s *Store[K, V]) Get(k K) V {\n\tdefer trace(\"__original__\")()\n\treturn s.__original__(k)\n}";
+        let resolved = resolve_original("method_declaration", patch, DEFAULT_RENAME_TO).unwrap();
+        assert_eq!(
+            resolved,
+            patch.replace("s.__original__(k)", "s.Get__replaced_by_method_decl(k)")
+        );
+
+        let resolved = resolve_original("method_declaration", patch, "orig{name}").unwrap();
+        assert!(resolved.contains("return s.origGet(k)"));
+        let result = Parser::<method_decl::MethodDeclPattern>::new(SOURCE)
+            .find_and_patch(|m| m.name == "Get", "{name}_{pattern}");
+        assert!(result.unwrap().contains(") Get_method_decl(k K)"));
+
+        // the original must exist to be wrapped
+        let manifest_patch = Patch {
+            pattern: Some("method_declaration".to_owned()),
+            patch_type: Some(PatchType::Wrap),
+            code: patch.replace("Get(", "Put("),
+            ..Default::default()
+        };
+        let result = try_run(
+            "method_declaration",
+            SOURCE.to_owned(),
+            &manifest_patch,
+            DEFAULT_RENAME_TO,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_delete_and_rename() {
+        let selector = |patch_type, pattern: &str, name: &str, receiver: Option<&str>| Patch {
+            pattern: Some(pattern.to_owned()),
+            patch_type: Some(patch_type),
+            name: Some(name.to_owned()),
+            receiver: receiver.map(str::to_owned),
+            ..Default::default()
+        };
+
+        let patch = selector(
+            PatchType::Rename,
+            "method_declaration",
+            "Len",
+            Some("*Store"),
+        );
+        let renamed = try_run(
+            "method_declaration",
+            SOURCE.to_owned(),
+            &patch,
+            DEFAULT_RENAME_TO,
+        )
+        .unwrap();
+        let expected = SOURCE.replace(") Len()", ") Len__replaced_by_method_decl()");
+        assert_eq!(renamed, Some(expected.clone()));
+
+        // renamed by a previous run already
+        let again = try_run("method_declaration", expected, &patch, DEFAULT_RENAME_TO).unwrap();
+        assert_eq!(again, renamed);
+
+        let patch = selector(
+            PatchType::Rename,
+            "method_declaration",
+            "Len",
+            Some("Other"),
+        );
+        assert!(try_run(
+            "method_declaration",
+            SOURCE.to_owned(),
+            &patch,
+            DEFAULT_RENAME_TO
+        )
+        .is_err());
+
+        let source = format!(
+            "{}\nfunc init() {{ a() }}\n\nfunc init() {{ b() }}\n",
+            SOURCE
+        );
+        let patch = selector(PatchType::Delete, "function_declaration", "init", None);
+        let deleted = try_run("function_declaration", source, &patch, DEFAULT_RENAME_TO)
+            .unwrap()
+            .unwrap();
+        assert!(!deleted.contains("init"));
+    }
+
+    #[test]
+    fn test_declares() {
+        let patch = |code: &str| Patch {
+            code: code.to_owned(),
+            ..Default::default()
+        };
+
+        let get = patch("func (s *Store[K, V]) Get(k K) V { panic(0) }");
+        assert!(declares(SOURCE, &get, DEFAULT_RENAME_TO).unwrap());
+        let renamed = SOURCE.replace("Get(", "Get__replaced_by_method_decl(");
+        assert!(declares(&renamed, &get, DEFAULT_RENAME_TO).unwrap());
+
+        let put = patch("func (s *Store[K, V]) Put(k K, v V) {}");
+        assert!(!declares(SOURCE, &put, DEFAULT_RENAME_TO).unwrap());
+
+        let len = Patch {
+            pattern: Some("method_declaration".to_owned()),
+            patch_type: Some(PatchType::Delete),
+            name: Some("Len".to_owned()),
+            receiver: Some("Other".to_owned()),
+            ..patch("")
+        };
+        assert!(!declares(SOURCE, &len, DEFAULT_RENAME_TO).unwrap());
+        let len = Patch {
+            receiver: Some("Store".to_owned()),
+            ..len
+        };
+        assert!(declares(SOURCE, &len, DEFAULT_RENAME_TO).unwrap());
+    }
+
+    #[test]
+    fn test_split_by_pattern() {
+        let code = r#"type (
+	Coin = sdk.Coin
+	Keeper struct{ coins []Coin }
+)
+
+func (k Keeper) Balance() Coin { return k.coins[0] }
+
+type Store interface{ Get() Coin }
+
+type IDs []string
+"#;
+        let split = split_by_pattern(code);
+        assert_eq!(
+            split,
+            vec![
+                (
+                    "type_declaration",
+                    "type Coin = sdk.Coin\n\ntype IDs []string".to_owned()
+                ),
+                (
+                    "struct_declaration",
+                    "type Keeper struct{ coins []Coin }".to_owned()
+                ),
+                (
+                    "method_declaration",
+                    "func (k Keeper) Balance() Coin { return k.coins[0] }".to_owned()
+                ),
+                (
+                    "interface_declaration",
+                    "type Store interface{ Get() Coin }".to_owned()
+                ),
+            ]
+        );
     }
 }
@@ -0,0 +1,106 @@
+use crate::patterns::{delete_name, Pattern};
+use tree_sitter::QueryMatch;
+
+// identifiers are matched without the `name:` field, so that every name of
+// a multi-name spec (`const a, b = 1, 2`) yields its own match
+const S_EXP: &str = r#"
+(source_file
+    (const_declaration
+        (const_spec
+            ((identifier) @name)
+        ) @spec
+    ) @const_decl
+)"#;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct ConstDeclPattern {
+    pub name: String,
+    pub spec: String,
+    pub const_itself: String,
+}
+
+impl Pattern for ConstDeclPattern {
+    const KIND: &'static str = "const_decl";
+
+    fn ident(&self) -> String {
+        self.name.to_owned()
+    }
+
+    fn sexp() -> &'static str {
+        S_EXP
+    }
+
+    fn from_match(matched: &QueryMatch, code: &str) -> Self {
+        let const_itself = &code[matched.captures[0].node.byte_range()];
+        let spec = &code[matched.captures[1].node.byte_range()];
+        let name = &code[matched.captures[2].node.byte_range()];
+
+        Self {
+            name: name.to_owned(),
+            spec: spec.to_owned(),
+            const_itself: const_itself.to_owned(),
+        }
+    }
+
+    fn append_suffix(matched: &QueryMatch, codebuf: &str, rename_to: &str) -> String {
+        let name_capture = matched.captures[2];
+        let name = &codebuf[name_capture.node.byte_range()];
+
+        let mut next = codebuf.to_owned();
+        next.replace_range(
+            name_capture.node.byte_range(),
+            &Self::renamed(name, rename_to),
+        );
+        next
+    }
+
+    // only the matched name goes away; in an iota group the spec is kept as `_`,
+    // so the values of the following specs are not renumbered
+    fn delete(matched: &QueryMatch, codebuf: &str) -> String {
+        let [decl, spec, name] = [0, 1, 2].map(|i| matched.captures[i].node);
+        delete_name(decl, spec, name, codebuf, true)
+    }
+
+    fn is_match(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConstDeclPattern;
+    use crate::patch::parser::Parser;
+    use crate::patterns::DEFAULT_RENAME_TO;
+
+    const SOURCE: &str = r#"package main
+
+const Version = "v1"
+
+const (
+	A = iota
+	B
+	C
+	D, E = 5, 6
+)
+"#;
+
+    #[test]
+    fn test_find_and_patch_in_group() {
+        let result = Parser::<ConstDeclPattern>::new(SOURCE)
+            .find_and_patch(|c| c.name == "E", DEFAULT_RENAME_TO);
+
+        let expected = SOURCE.replace("D, E = 5, 6", "D, E__replaced_by_const_decl = 5, 6");
+        assert_eq!(result, Some(expected));
+    }
+
+    #[test]
+    fn test_find_and_delete_keeps_iota() {
+        let parser = Parser::<ConstDeclPattern>::new(SOURCE);
+
+        let result = parser.find_and_delete(|c| c.name == "B");
+        assert_eq!(result, Some(SOURCE.replace("\tB\n", "\t_\n")));
+
+        let result = parser.find_and_delete(|c| c.name == "Version");
+        assert_eq!(result, Some(SOURCE.replace("const Version = \"v1\"", "")));
+    }
+}
@@ -4,22 +4,24 @@ const S_EXP: &str = r#"
 (source_file
     (function_declaration
         name: (identifier) @name
+        type_parameters: (type_parameter_list)? @type_params
         parameters: (parameter_list) @params
-        result: (type_identifier)? @return
+        result: (_)? @return
     ) @func_decl
 )+"#;
 
-const REPLACE_SUFFIX: &str = "_replaced_by_function_decl";
-
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct FunctionDeclPattern {
     pub name: String,
+    pub type_params: String,
     pub param_t: String,
     pub return_t: String,
     pub fn_itself: String,
 }
 
 impl Pattern for FunctionDeclPattern {
+    const KIND: &'static str = "function_decl";
+
     fn ident(&self) -> String {
         self.name.clone()
     }
@@ -29,30 +31,35 @@ impl Pattern for FunctionDeclPattern {
     }
 
     fn from_match(matched: &tree_sitter::QueryMatch, code: &str) -> Self {
-        let fn_itself = &code[matched.captures[0].node.byte_range()];
-        let fn_name = &code[matched.captures[1].node.byte_range()];
-        let fn_param_t = &code[matched.captures[2].node.byte_range()];
-        let fn_return_t = match matched.captures.get(3) {
-            Some(cap) => &code[cap.node.byte_range()],
+        let fn_node = matched.captures[0].node;
+        let field = |name: &str| match fn_node.child_by_field_name(name) {
+            Some(node) => &code[node.byte_range()],
             None => "",
         };
 
+        let fn_itself = &code[fn_node.byte_range()];
+        let fn_name = field("name");
+        let fn_type_params = field("type_parameters");
+        let fn_param_t = field("parameters");
+        let fn_return_t = field("result");
+
         Self {
             name: fn_name.to_string(),
+            type_params: fn_type_params.to_string(),
             param_t: fn_param_t.to_string(),
             return_t: fn_return_t.to_string(),
             fn_itself: fn_itself.to_string(),
         }
     }
 
-    fn append_suffix(matched: &tree_sitter::QueryMatch, codebuf: &str) -> String {
+    fn append_suffix(matched: &tree_sitter::QueryMatch, codebuf: &str, rename_to: &str) -> String {
         let fn_name_capture = matched.captures[1];
         let fn_name = &codebuf[fn_name_capture.node.byte_range()];
 
         let mut next = codebuf.to_string();
         next.replace_range(
             fn_name_capture.node.byte_range(),
-            &format!("{}_{}", fn_name, REPLACE_SUFFIX),
+            &Self::renamed(fn_name, rename_to),
         );
         next
     }
@@ -69,3 +76,39 @@ impl Pattern for FunctionDeclPattern {
         self.name == other.name
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::FunctionDeclPattern;
+    use crate::manifest::Manifest;
+    use crate::patch::parser::Parser;
+    use crate::patterns::{Pattern, DEFAULT_RENAME_TO};
+
+    const SOURCE: &str =
+        "package main\n\nfunc Map[T any, U any](xs []T, f func(T) U) []U {\n\treturn nil\n}\n";
+
+    #[test]
+    fn test_generic_function() {
+        let patch = "func Map[A, B any](xs []A, f func(A) B) []B {\n\treturn mapped(xs, f)\n}";
+        let target = Parser::<FunctionDeclPattern>::new(patch)
+            .find_first_match()
+            .unwrap();
+        assert_eq!(target.name, "Map");
+        assert_eq!(target.type_params, "[A, B any]");
+        assert_eq!(target.return_t, "[]B");
+
+        let renamed = SOURCE.replace("func Map[", "func Map__replaced_by_function_decl[");
+        let result = Parser::<FunctionDeclPattern>::new(SOURCE)
+            .find_and_patch(|f| f.is_match(&target), DEFAULT_RENAME_TO);
+        assert_eq!(result, Some(renamed.clone()));
+
+        let manifest: Manifest = serde_yaml::from_str(&format!(
+            "file: main.go\npatch:\n  - code: |\n      {}\n",
+            patch.replace('\n', "\n      ")
+        ))
+        .unwrap();
+        let patched = crate::try_patch(SOURCE.to_owned(), &manifest).unwrap();
+        assert_eq!(patched.code, renamed);
+        assert_eq!(patched.patches, vec![format!("{}\n", patch)]);
+    }
+}
@@ -1,11 +1,349 @@
-// use crate::patterns::{Pattern};
-//
-// const S_EXP: &str = r#"
-// (source_file
-//     (import_declaration
-//         (import_clause
-//             (identifier) @import_name
-//             (string) @import_path
-//         )
-//     )
-// )"#;
+use crate::manifest::ManifestImport;
+use crate::patch::parser::{query, Parser};
+use crate::patterns::line_range;
+use crate::patterns::module_decl::ModuleDeclPattern;
+use crate::{Error, Result};
+use std::collections::HashSet;
+use std::ops::Range;
+use tree_sitter::QueryMatch;
+
+const S_EXP: &str = r#"
+(source_file
+    (import_declaration
+        [
+            (import_spec) @spec
+            (import_spec_list (import_spec) @spec)
+        ]
+    ) @import_decl
+)"#;
+
+/// ImportSpec is a single import spec, either standalone or inside a grouped declaration.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct ImportSpec {
+    pub alias: Option<String>,
+    pub path: String,
+    pub spec_range: Range<usize>,
+    pub decl_range: Range<usize>,
+
+    // byte offset of the closing paren, if the spec belongs to a grouped declaration
+    pub group_end: Option<usize>,
+}
+
+impl ImportSpec {
+    pub fn find_all(code: &str) -> Vec<Self> {
+        query(code, S_EXP, Self::from_match)
+    }
+
+    fn from_match(matched: &QueryMatch, code: &str) -> Self {
+        let decl = matched.captures[0].node;
+        let spec = matched.captures[1].node;
+
+        let alias = spec
+            .child_by_field_name("name")
+            .map(|name| code[name.byte_range()].to_owned());
+        let path = spec
+            .child_by_field_name("path")
+            .map(|path| {
+                code[path.byte_range()]
+                    .trim_matches(|c| c == '"' || c == '`')
+                    .to_owned()
+            })
+            .unwrap_or_default();
+
+        // the spec list ends with the closing paren
+        let group_end = (spec.parent().map(|p| p.kind()) == Some("import_spec_list"))
+            .then(|| spec.parent().unwrap().end_byte() - 1);
+
+        Self {
+            alias,
+            path,
+            spec_range: spec.byte_range(),
+            decl_range: decl.byte_range(),
+            group_end,
+        }
+    }
+}
+
+// PACKAGE_REF_S_EXP matches references to imported packages, e.g. `fmt` in `fmt.Println` or `a.Pointer`
+const PACKAGE_REF_S_EXP: &str = r#"
+[
+    (selector_expression operand: (identifier) @package)
+    (qualified_type package: (package_identifier) @package)
+]"#;
+
+// package_refs collects the names of the packages referenced in code
+fn package_refs(code: &str) -> HashSet<String> {
+    query(code, PACKAGE_REF_S_EXP, |matched, code| {
+        code[matched.captures[0].node.byte_range()].to_owned()
+    })
+    .into_iter()
+    .collect()
+}
+
+/// merge_imports adds every import not yet present in the code,
+/// preferably into the last grouped import declaration.
+pub fn merge_imports(file: &str, code: &str, imports: &[ManifestImport]) -> Result<String> {
+    let existing = ImportSpec::find_all(code);
+
+    // every (path, alias) pair imported so far, including the ones about to be added
+    let mut present = existing
+        .iter()
+        .map(|spec| (spec.path.to_owned(), own_alias(&spec.path, &spec.alias)))
+        .collect::<Vec<_>>();
+
+    // collect the imports that are actually missing
+    let mut missing: Vec<&ManifestImport> = Vec::new();
+    for import in imports {
+        let aliases = present
+            .iter()
+            .filter(|(path, _)| *path == import.path)
+            .map(|(_, alias)| alias)
+            .collect::<Vec<_>>();
+
+        // a blank import only exists for its side effects, which any other import of the path has too
+        if import.is_blank() && !aliases.is_empty() {
+            continue;
+        }
+
+        // ...and likewise, a blank import never binds a name to conflict with
+        let aliases = aliases
+            .into_iter()
+            .filter(|alias| alias.as_deref() != Some("_"))
+            .collect::<Vec<_>>();
+
+        // same path under the same alias is a no-op; under a different one it's a conflict
+        let alias = own_alias(&import.path, &import.alias);
+        if aliases.contains(&&alias) {
+            continue;
+        }
+
+        if let Some(present_alias) = aliases.first() {
+            return Err(Error::ImportConflict {
+                file: file.to_owned(),
+                path: import.path.to_owned(),
+                aliases: [
+                    present_alias.as_deref().unwrap_or_default().to_owned(),
+                    import.alias.as_deref().unwrap_or_default().to_owned(),
+                ],
+            });
+        }
+
+        present.push((import.path.to_owned(), alias));
+        missing.push(import);
+    }
+
+    if missing.is_empty() {
+        return Ok(code.to_owned());
+    }
+
+    let rendered = missing
+        .iter()
+        .fold(String::new(), |acc, imp| acc + "\t" + &render(imp) + "\n");
+
+    let mut next = code.to_owned();
+
+    // 1. append into the last grouped import declaration
+    if let Some(group_end) = existing.iter().filter_map(|spec| spec.group_end).max() {
+        let prefix = match code[..group_end].ends_with('\n') {
+            true => "",
+            false => "\n",
+        };
+        next.insert_str(group_end, &format!("{prefix}{rendered}"));
+        return Ok(next);
+    }
+
+    // 2. add a new grouped declaration after the last import declaration, or
+    // 3. right after the package clause if there are no imports at all
+    let at = match existing.iter().map(|spec| spec.decl_range.end).max() {
+        Some(end) => end,
+        None => {
+            Parser::<ModuleDeclPattern>::new(code)
+                .find_next_line()
+                .ok_or_else(|| Error::Parse {
+                    file: file.to_owned(),
+                    reason: "file does not contain any module declaration".to_owned(),
+                })?
+                .end
+        }
+    };
+    next.insert_str(at, &format!("\n\nimport (\n{rendered})"));
+
+    Ok(next)
+}
+
+/// prune_imports removes the imports that were referenced in the original code, but no longer are.
+///
+/// imports never referenced in the first place are left alone, as the package name
+/// guessed from the import path might simply be wrong for them.
+pub fn prune_imports(original: &str, code: &str) -> String {
+    let (was_referenced, is_referenced) = (package_refs(original), package_refs(code));
+
+    let specs = ImportSpec::find_all(code);
+    let unused = specs
+        .iter()
+        .filter(|spec| match package_name(spec) {
+            Some(name) => was_referenced.contains(&name) && !is_referenced.contains(&name),
+            None => false,
+        })
+        .collect::<Vec<_>>();
+
+    // remove the whole declaration if none of its specs survive, otherwise just the spec's line
+    let mut ranges = unused
+        .iter()
+        .map(|spec| {
+            let emptied = specs
+                .iter()
+                .filter(|other| other.decl_range == spec.decl_range)
+                .all(|other| unused.contains(&other));
+
+            match emptied {
+                true => line_range(code, &spec.decl_range),
+                false => line_range(code, &spec.spec_range),
+            }
+        })
+        .collect::<Vec<_>>();
+    ranges.sort_by_key(|range| std::cmp::Reverse(range.start));
+    ranges.dedup();
+
+    let mut next = code.to_owned();
+    for range in ranges {
+        next.replace_range(range, "");
+    }
+    next
+}
+
+// package_name guesses the name an import is referenced by; none for blank and dot imports
+fn package_name(spec: &ImportSpec) -> Option<String> {
+    match &spec.alias {
+        Some(alias) => (alias != "_" && alias != ".").then(|| alias.to_owned()),
+        None => path_name(&spec.path),
+    }
+}
+
+// own_alias drops an alias that is the package's own name anyway, e.g. `fmt "fmt"`
+fn own_alias(path: &str, alias: &Option<String>) -> Option<String> {
+    alias
+        .to_owned()
+        .filter(|alias| path_name(path).as_ref() != Some(alias))
+}
+
+// path_name guesses the name of a package from its import path,
+// e.g. github.com/cosmos/cosmos-sdk/x/bank/v2 or gopkg.in/yaml.v3
+fn path_name(path: &str) -> Option<String> {
+    let mut segments = path.rsplit('/');
+    let last = segments.next()?;
+    let is_major =
+        |s: &str| s.len() > 1 && s.starts_with('v') && s[1..].bytes().all(|b| b.is_ascii_digit());
+    let last = match is_major(last) {
+        true => segments.next().unwrap_or(last),
+        false => last,
+    };
+    let last = match last.rsplit_once('.') {
+        Some((name, major)) if is_major(major) => name,
+        _ => last,
+    };
+
+    Some(last.trim_start_matches("go-").replace('-', "_"))
+}
+
+// render renders a single import spec, e.g. `aaa "github.com/fake-organization/pkg_a"`
+fn render(import: &ManifestImport) -> String {
+    match &import.alias {
+        Some(alias) => format!("{} \"{}\"", alias, import.path),
+        None => format!("\"{}\"", import.path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_imports, prune_imports};
+    use crate::manifest::ManifestImport;
+    use crate::Error;
+
+    fn import(alias: &str, path: &str) -> ManifestImport {
+        ManifestImport {
+            alias: (!alias.is_empty()).then(|| alias.to_owned()),
+            path: path.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_merge_into_existing_block() {
+        let code = "package main\n\nimport (\n\tfmt \"fmt\"\n)\n";
+        let imports = [
+            import("fmt", "fmt"),
+            import("aaa", "github.com/fake-organization/pkg_a"),
+            import("aaa", "github.com/fake-organization/pkg_a"),
+        ];
+
+        let expected = "package main\n\nimport (\n\tfmt \"fmt\"\n\taaa \"github.com/fake-organization/pkg_a\"\n)\n";
+        assert_eq!(merge_imports("main.go", code, &imports).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_merge_without_imports() {
+        let code = "package main\n\nfunc main() {}\n";
+
+        assert_eq!(merge_imports("main.go", code, &[]).unwrap(), code);
+
+        let expected = "package main\n\nimport (\n\tfmt \"fmt\"\n)\n\nfunc main() {}\n";
+        assert_eq!(
+            merge_imports("main.go", code, &[import("fmt", "fmt")]).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_merge_conflicting_alias() {
+        let code = "package main\n\nimport a \"unsafe\"\n";
+
+        let result = merge_imports("main.go", code, &[import("b", "unsafe")]);
+        assert!(matches!(result, Err(Error::ImportConflict { .. })));
+
+        let result = merge_imports(
+            "main.go",
+            "package main\n",
+            &[import("a", "unsafe"), import("b", "unsafe")],
+        );
+        assert!(matches!(result, Err(Error::ImportConflict { .. })));
+    }
+
+    #[test]
+    fn test_merge_alias_forms() {
+        let code = "package main\n\nimport (\n\t\"fmt\"\n\t_ \"embed\"\n)\n";
+        let imports = [
+            import("", "fmt"),
+            import("_", "fmt"),
+            import("", "embed"),
+            import("_", "github.com/cosmos/cosmos-sdk/codec/types"),
+            import(".", "github.com/fake-organization/pkg_a"),
+        ];
+
+        let expected = "package main\n\nimport (\n\t\"fmt\"\n\t_ \"embed\"\n\t\"embed\"\n\t_ \"github.com/cosmos/cosmos-sdk/codec/types\"\n\t. \"github.com/fake-organization/pkg_a\"\n)\n";
+        assert_eq!(merge_imports("main.go", code, &imports).unwrap(), expected);
+
+        let result = merge_imports("main.go", code, &[import("f", "fmt")]);
+        assert!(matches!(result, Err(Error::ImportConflict { .. })));
+
+        // an alias naming the package by its own name is no alias at all
+        assert_eq!(
+            merge_imports("main.go", code, &[import("fmt", "fmt")]).unwrap(),
+            code
+        );
+        let aliased = "package main\n\nimport (\n\tyaml \"gopkg.in/yaml.v3\"\n)\n";
+        assert_eq!(
+            merge_imports("main.go", aliased, &[import("", "gopkg.in/yaml.v3")]).unwrap(),
+            aliased
+        );
+    }
+
+    #[test]
+    fn test_prune_imports() {
+        let original = "package main\n\nimport (\n\t\"fmt\"\n\t\"strings\"\n\t\"os\"\n)\n\nimport b \"unsafe\"\n\nvar p b.Pointer\n\nfunc main() {\n\tfmt.Println(strings.ToUpper(\"x\"))\n}\n";
+        let code = "package main\n\nimport (\n\t\"fmt\"\n\t\"strings\"\n\t\"os\"\n)\n\nimport b \"unsafe\"\n\nfunc main() {\n\tfmt.Println(\"x\")\n}\n";
+
+        // os was never referenced; only strings and unsafe became unused
+        let expected = "package main\n\nimport (\n\t\"fmt\"\n\t\"os\"\n)\n\n\nfunc main() {\n\tfmt.Println(\"x\")\n}\n";
+        assert_eq!(prune_imports(original, code), expected);
+    }
+}
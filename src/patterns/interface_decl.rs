@@ -1,13 +1,19 @@
-use crate::patterns::Pattern;
+use crate::manifest::PatchType;
+use crate::patterns::members::edit_members;
+use crate::patterns::{delete_type_spec, Pattern};
+use crate::Result;
 use tree_sitter::QueryMatch;
 
 pub struct InterfaceDeclPattern {
     pub name: String,
+    pub type_params: String,
     pub fields: Vec<String>,
     pub interface_itself: String,
 }
 
 impl Pattern for InterfaceDeclPattern {
+    const KIND: &'static str = "interface_decl";
+
     fn ident(&self) -> String {
         self.name.to_owned()
     }
@@ -18,6 +24,7 @@ impl Pattern for InterfaceDeclPattern {
 	(type_declaration
     	(type_spec
         	name: (type_identifier) @name
+            type_parameters: (type_parameter_list)? @type_params
             type: (interface_type
             	(method_elem
                 	name: [(field_identifier)] @field_name
@@ -31,7 +38,19 @@ impl Pattern for InterfaceDeclPattern {
     fn from_match(matched: &QueryMatch, code: &str) -> Self {
         let interface_itself = &code[matched.captures[0].node.byte_range()];
         let struct_name = &code[matched.captures[1].node.byte_range()];
-        let fields = match matched.captures.get(2) {
+        let type_params = match matched
+            .captures
+            .iter()
+            .find(|cap| cap.node.kind() == "type_parameter_list")
+        {
+            Some(cap) => &code[cap.node.byte_range()],
+            None => "",
+        };
+        let fields = match matched
+            .captures
+            .iter()
+            .find(|cap| cap.node.kind() == "field_identifier")
+        {
             Some(cap) => &code[cap.node.byte_range()],
             None => "",
         };
@@ -39,31 +58,106 @@ impl Pattern for InterfaceDeclPattern {
         Self {
             interface_itself: interface_itself.to_owned(),
             name: struct_name.to_owned(),
+            type_params: type_params.to_owned(),
             fields: vec![fields.to_owned()],
         }
     }
 
-    fn append_suffix(matched: &QueryMatch, codebuf: &str) -> String {
+    fn append_suffix(matched: &QueryMatch, codebuf: &str, rename_to: &str) -> String {
         let struct_name_capture = matched.captures[1];
         let struct_name = &codebuf[struct_name_capture.node.byte_range()];
 
         let mut next = codebuf.to_owned();
         next.replace_range(
             struct_name_capture.node.byte_range(),
-            &format!("{}_{}", struct_name, "_replaced_by_interface_decl"),
+            &Self::renamed(struct_name, rename_to),
         );
         next
     }
 
+    // removes the whole declaration, or only the spec inside a type ( ... ) group
     fn delete(matched: &QueryMatch, codebuf: &str) -> String {
-        let interface_capture = matched.captures[0];
-
-        let mut next = codebuf.to_owned();
-        next.replace_range(interface_capture.node.byte_range(), "");
-        next
+        let spec = matched.captures[1].node.parent().unwrap();
+        delete_type_spec(codebuf, spec)
     }
 
     fn is_match(&self, other: &Self) -> bool {
         self.name == other.name
     }
 }
+
+// edit_methods adds, removes or replaces individual methods of an interface in place.
+// the patch is an interface declaration holding only the methods to edit.
+pub fn edit_methods(code: &str, patch: &str, patch_type: &PatchType) -> Result<String> {
+    edit_members::<InterfaceDeclPattern>("interface_declaration", code, patch, patch_type, |ty| {
+        Some(ty)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edit_methods, InterfaceDeclPattern};
+    use crate::manifest::PatchType;
+    use crate::patch::parser::Parser;
+    use crate::patterns::{Pattern, DEFAULT_RENAME_TO};
+
+    const CODE: &str = r#"package main
+
+type Store interface {
+	io.Closer
+	Get(key string) ([]byte, error)
+	Set(key string, value []byte) error // overwrites
+}
+
+type Empty interface{}
+"#;
+
+    #[test]
+    fn test_add_and_remove_method() {
+        let patch = "type Store interface {\n\tDelete(key string) error\n\tGet(key string) ([]byte, error)\n}";
+        let added = edit_methods(CODE, patch, &PatchType::AddMethod).unwrap();
+        assert!(added.contains("// overwrites\n\tDelete(key string) error\n}"));
+        assert_eq!(added.matches("Get(key string)").count(), 1);
+
+        let patch = "type Empty interface {\n\tString() string\n}";
+        let added = edit_methods(CODE, patch, &PatchType::AddMethod).unwrap();
+        assert!(added.contains("type Empty interface{\n\tString() string\n}"));
+
+        let patch = "type Store interface {\n\tSet()\n\tio.Closer\n}";
+        let removed = edit_methods(CODE, patch, &PatchType::RemoveMethod).unwrap();
+        assert!(removed.contains("type Store interface {\n\tGet(key string) ([]byte, error)\n}"));
+    }
+
+    #[test]
+    fn test_replace_method() {
+        let patch =
+            "type Store interface {\n\tGet(ctx context.Context, key string) ([]byte, error)\n}";
+        let replaced = edit_methods(CODE, patch, &PatchType::ReplaceMethod).unwrap();
+        assert!(replaced.contains("\tGet(ctx context.Context, key string) ([]byte, error)\n\tSet("));
+
+        let patch = "type Store interface {\n\tHas(key string) bool\n}";
+        assert!(edit_methods(CODE, patch, &PatchType::ReplaceMethod).is_err());
+    }
+
+    #[test]
+    fn test_generic_interface() {
+        let source = "package main\n\ntype Container[T any] interface {\n\tGet() T\n}\n";
+        let patch = "type Container[T any] interface {\n\tGet() T\n\tPut(v T)\n}";
+        let target = Parser::<InterfaceDeclPattern>::new(patch)
+            .find_first_match()
+            .unwrap();
+        assert_eq!(target.name, "Container");
+        assert_eq!(target.type_params, "[T any]");
+
+        let result = Parser::<InterfaceDeclPattern>::new(source)
+            .find_and_patch(|i| i.is_match(&target), DEFAULT_RENAME_TO);
+        let renamed = source.replace(
+            "type Container[",
+            "type Container__replaced_by_interface_decl[",
+        );
+        assert_eq!(result, Some(renamed));
+
+        let added = edit_methods(source, patch, &PatchType::AddMethod).unwrap();
+        assert!(added.contains("\tGet() T\n\tPut(v T)\n}"));
+    }
+}
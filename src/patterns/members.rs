@@ -0,0 +1,234 @@
+use crate::manifest::PatchType;
+use crate::patch::parser::Parser;
+use crate::patterns::{base_type, indent_at, line_range, Pattern};
+use crate::{Error, Result};
+use log::info;
+use std::ops::Range;
+use tree_sitter::Node;
+
+// Member is a single entry in between the braces of a struct or an interface,
+// e.g. `X, Y int`, `io.Reader` or `Close() error`
+pub(crate) struct Member {
+    pub names: Vec<(String, Range<usize>)>,
+    pub range: Range<usize>,
+}
+
+impl Member {
+    pub fn has_name(&self, name: &str) -> bool {
+        self.names.iter().any(|(n, _)| n == name)
+    }
+}
+
+// members lists the members declared in list; embedded types are named after their base type
+pub(crate) fn members(list: Node, code: &str) -> Vec<Member> {
+    let mut cursor = list.walk();
+    let children = list.named_children(&mut cursor).collect::<Vec<_>>();
+
+    children
+        .into_iter()
+        .filter_map(|child| {
+            let names = match child.kind() {
+                "field_declaration" | "method_elem" => {
+                    let mut cursor = child.walk();
+                    let names = child
+                        .children_by_field_name("name", &mut cursor)
+                        .map(|name| (code[name.byte_range()].to_owned(), name.byte_range()))
+                        .collect::<Vec<_>>();
+
+                    match names.is_empty() {
+                        true => embedded(child.child_by_field_name("type")?, code),
+                        false => names,
+                    }
+                }
+                "type_elem" => embedded(child.named_child(0)?, code),
+                // comments
+                _ => return None,
+            };
+
+            Some(Member {
+                names,
+                range: child.byte_range(),
+            })
+        })
+        .collect()
+}
+
+fn embedded(ty: Node, code: &str) -> Vec<(String, Range<usize>)> {
+    vec![(base_type(ty, code).to_owned(), ty.byte_range())]
+}
+
+// edit_members adds, removes or replaces individual members of the declaration
+// matching the one in the patch, in place. list finds the node holding the members
+// within the type of the declaration, e.g. the field list of a struct type.
+pub(crate) fn edit_members<P: Pattern>(
+    pattern: &str,
+    code: &str,
+    patch: &str,
+    patch_type: &PatchType,
+    list: fn(Node) -> Option<Node>,
+) -> Result<String> {
+    let patch_parser = Parser::<P>::new(patch);
+    let target = patch_parser
+        .find_first_match()
+        .ok_or_else(|| Error::PatternNotFound {
+            pattern: pattern.to_owned(),
+            code: patch.to_owned(),
+        })?;
+    let name = target.ident();
+    let patch_members = patch_parser
+        .find_node(|pat| pat.is_match(&target))
+        .and_then(|decl| spec_type(decl, &name, patch))
+        .and_then(list)
+        .map(|list| members(list, patch))
+        .unwrap_or_default();
+
+    // members are edited one at a time, re-parsing the code in between
+    patch_members
+        .iter()
+        .try_fold(code.to_owned(), |code, member| {
+            let names = member
+                .names
+                .iter()
+                .map(|(name, _)| name.to_owned())
+                .collect::<Vec<_>>();
+            let text = &patch[member.range.clone()];
+
+            let parser = Parser::<P>::new(&code);
+            let list = parser
+                .find_node(|pat| pat.is_match(&target))
+                .and_then(|decl| spec_type(decl, &name, &code))
+                .and_then(list)
+                .ok_or_else(|| Error::SymbolNotFound {
+                    pattern: pattern.to_owned(),
+                    name: name.to_owned(),
+                })?;
+            let existing = members(list, &code);
+            let found = existing
+                .iter()
+                .find(|member| names.iter().any(|name| member.has_name(name)));
+
+            Ok(match (patch_type, found) {
+                (PatchType::AddField | PatchType::AddMethod, None) => add_member(&code, list, text),
+                (PatchType::RemoveField | PatchType::RemoveMethod, Some(member)) => {
+                    remove_names(&code, member, &names)
+                }
+                (PatchType::ReplaceField | PatchType::ReplaceMethod, Some(member)) => {
+                    replace_member(&code, member, &names, text)
+                }
+                (PatchType::ReplaceField | PatchType::ReplaceMethod, None) => {
+                    return Err(Error::SymbolNotFound {
+                        pattern: pattern.to_owned(),
+                        name: format!("{}.{}", name, names.join(", ")),
+                    })
+                }
+                // already added or removed by a previous run
+                _ => {
+                    info!(
+                        "{}: {}.{} is up to date",
+                        patch_type,
+                        name,
+                        names.join(", ")
+                    );
+                    code.to_owned()
+                }
+            })
+        })
+}
+
+// spec_type finds the type of the spec named name within a type declaration
+fn spec_type<'tree>(decl: Node<'tree>, name: &str, code: &str) -> Option<Node<'tree>> {
+    let mut cursor = decl.walk();
+    let spec = decl.named_children(&mut cursor).find(|spec| {
+        spec.child_by_field_name("name")
+            .map_or(false, |n| &code[n.byte_range()] == name)
+    })?;
+
+    spec.child_by_field_name("type")
+}
+
+// add_member inserts text as the last member of list, on its own line
+pub(crate) fn add_member(code: &str, list: Node, text: &str) -> String {
+    let close = list.child(list.child_count() - 1).unwrap();
+    let before = &code[..close.start_byte()];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+    let outer = indent_at(code, list.start_byte());
+    let indent = match members(list, code).first() {
+        Some(first) => indent_at(code, first.range.start),
+        None => format!("{}\t", outer),
+    };
+
+    let mut next = code.to_owned();
+    match before[line_start..].trim().is_empty() {
+        // the closing brace is on its own line
+        true => next.insert_str(line_start, &format!("{}{}\n", indent, text)),
+        // e.g. `struct{}`
+        false => next.insert_str(
+            close.start_byte(),
+            &format!("\n{}{}\n{}", indent, text, outer),
+        ),
+    }
+    next
+}
+
+// remove_names removes names from member, removing its whole line if none is left
+pub(crate) fn remove_names(code: &str, member: &Member, names: &[String]) -> String {
+    let mut next = code.to_owned();
+    match without_names(code, member, names) {
+        Some(rest) => next.replace_range(member.range.clone(), &rest),
+        None => next.replace_range(with_comment(code, line_range(code, &member.range)), ""),
+    }
+    next
+}
+
+// replace_member replaces the member declaring names with text,
+// keeping whatever other names it declares along with it
+pub(crate) fn replace_member(code: &str, member: &Member, names: &[String], text: &str) -> String {
+    let mut next = code.to_owned();
+    match without_names(code, member, names) {
+        Some(rest) => {
+            let indent = indent_at(code, member.range.start);
+            next.replace_range(
+                member.range.clone(),
+                &format!("{}\n{}{}", rest, indent, text),
+            )
+        }
+        None => next.replace_range(member.range.clone(), text),
+    }
+    next
+}
+
+// without_names renders member with names left out; None if no name is left
+fn without_names(code: &str, member: &Member, names: &[String]) -> Option<String> {
+    let remaining = member
+        .names
+        .iter()
+        .filter(|(name, _)| !names.contains(name))
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    let (_, last) = member.names.last()?;
+
+    match remaining.is_empty() {
+        true => None,
+        false => Some(format!(
+            "{}{}",
+            remaining.join(", "),
+            &code[last.end..member.range.end]
+        )),
+    }
+}
+
+// with_comment extends a line range over a trailing `// comment` or whitespace
+fn with_comment(code: &str, range: Range<usize>) -> Range<usize> {
+    if code[..range.end].ends_with('\n') {
+        return range;
+    }
+
+    let rest = &code[range.end..];
+    let line_end = rest.find('\n').map_or(rest.len(), |i| i + 1);
+    let tail = rest[..line_end].trim_start();
+    match tail.is_empty() || tail.starts_with("//") {
+        true => range.start..range.end + line_end,
+        false => range,
+    }
+}
@@ -1,4 +1,4 @@
-use crate::patterns::Pattern;
+use crate::patterns::{base_type, Pattern};
 
 const S_EXP: &str = r#"
 (source_file
@@ -8,15 +8,17 @@ const S_EXP: &str = r#"
         )
         name: (field_identifier) @name
         parameters: (parameter_list) @params
-        result: (type_identifier)? @return
+        result: (_)? @return
     ) @method_decl
 )+"#;
 
-const REPLACE_SUFFIX: &str = "_replaced_by_method_decl";
-
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct MethodDeclPattern {
     pub receiver: String,
+
+    // receiver_type is the receiver's base type, without pointer or type parameters;
+    // e.g. `Store` for both `(s *Store[K, V])` and `(Store[A, B])`
+    pub receiver_type: String,
     pub name: String,
     pub param_t: String,
     pub return_t: String,
@@ -24,26 +26,41 @@ pub struct MethodDeclPattern {
 }
 
 impl Pattern for MethodDeclPattern {
+    const KIND: &'static str = "method_decl";
+
     fn ident(&self) -> String {
         self.name.clone()
     }
 
+    fn receiver(&self) -> Option<String> {
+        Some(self.receiver_type.to_owned())
+    }
+
     fn sexp() -> &'static str {
         S_EXP
     }
 
     fn from_match(matched: &tree_sitter::QueryMatch, code: &str) -> Self {
-        let method_itself = &code[matched.captures[0].node.byte_range()];
-        let receiver = &code[matched.captures[1].node.byte_range()];
-        let fn_name = &code[matched.captures[2].node.byte_range()];
-        let fn_param_t = &code[matched.captures[3].node.byte_range()];
-        let fn_return_t = match matched.captures.get(4) {
-            Some(cap) => &code[cap.node.byte_range()],
+        let method_node = matched.captures[0].node;
+        let field = |name: &str| match method_node.child_by_field_name(name) {
+            Some(node) => &code[node.byte_range()],
             None => "",
         };
 
+        let method_itself = &code[method_node.byte_range()];
+        let receiver_node = matched.captures[1].node;
+        let receiver = &code[receiver_node.byte_range()];
+        let receiver_type = receiver_node
+            .child_by_field_name("type")
+            .map(|node| base_type(node, code))
+            .unwrap_or_default();
+        let fn_name = field("name");
+        let fn_param_t = field("parameters");
+        let fn_return_t = field("result");
+
         Self {
             receiver: receiver.to_string(),
+            receiver_type: receiver_type.to_string(),
             name: fn_name.to_string(),
             param_t: fn_param_t.to_string(),
             return_t: fn_return_t.to_string(),
@@ -51,14 +68,14 @@ impl Pattern for MethodDeclPattern {
         }
     }
 
-    fn append_suffix(matched: &tree_sitter::QueryMatch, codebuf: &str) -> String {
+    fn append_suffix(matched: &tree_sitter::QueryMatch, codebuf: &str, rename_to: &str) -> String {
         let fn_name_capture = matched.captures[2];
         let fn_name = &codebuf[fn_name_capture.node.byte_range()];
 
         let mut next = codebuf.to_string();
         next.replace_range(
             fn_name_capture.node.byte_range(),
-            &format!("{}_{}", fn_name, REPLACE_SUFFIX),
+            &Self::renamed(fn_name, rename_to),
         );
         next
     }
@@ -71,7 +88,40 @@ impl Pattern for MethodDeclPattern {
         next
     }
 
+    // go does not allow the same method name on both T and *T,
+    // so the receiver's base type is all that identifies the method
     fn is_match(&self, other: &Self) -> bool {
-        (self.name == other.name) && (self.receiver == other.receiver)
+        (self.name == other.name) && (self.receiver_type == other.receiver_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MethodDeclPattern;
+    use crate::patch::parser::Parser;
+    use crate::patterns::{Pattern, DEFAULT_RENAME_TO};
+
+    const SOURCE: &str = r#"package main
+
+type Store[K comparable, V any] struct{ m map[K]V }
+
+func (s *Store[K, V]) Get(k K) V { return s.m[k] }
+
+func (s Store[K, V]) Len() int { return len(s.m) }
+"#;
+
+    #[test]
+    fn test_generic_receiver() {
+        let patch = "func (st *Store[Key, Value]) Get(k Key) Value { var v Value; return v }";
+        let target = Parser::<MethodDeclPattern>::new(patch)
+            .find_first_match()
+            .unwrap();
+        assert_eq!(target.receiver_type, "Store");
+        assert_eq!(target.return_t, "Value");
+
+        let result = Parser::<MethodDeclPattern>::new(SOURCE)
+            .find_and_patch(|m| m.is_match(&target), DEFAULT_RENAME_TO);
+        let expected = SOURCE.replace(") Get(", ") Get__replaced_by_method_decl(");
+        assert_eq!(result, Some(expected));
     }
 }
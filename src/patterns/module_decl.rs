@@ -11,6 +11,9 @@ pub struct ModuleDeclPattern {
 }
 
 impl Pattern for ModuleDeclPattern {
+    // never renamed
+    const KIND: &'static str = "module_decl";
+
     fn ident(&self) -> String {
         self.name.clone()
     }
@@ -27,7 +30,7 @@ impl Pattern for ModuleDeclPattern {
         }
     }
 
-    fn append_suffix(_: &tree_sitter::QueryMatch, _: &str) -> String {
+    fn append_suffix(_: &tree_sitter::QueryMatch, _: &str, _: &str) -> String {
         panic!("ModuleDeclPattern::replace() not implemented")
     }
 
@@ -0,0 +1,440 @@
+use crate::manifest::{Patch, PatchType};
+use crate::patch::parser::Parser;
+use crate::patterns::{indent_at, Pattern};
+use crate::{Error, Result};
+use log::info;
+use tree_sitter::Node;
+
+// nodes whose children are statements
+const STATEMENT_PARENTS: &[&str] = &[
+    "block",
+    "expression_case",
+    "type_case",
+    "default_case",
+    "communication_case",
+];
+
+// insert_statements injects the statements in the body of the patch function into the body
+// of the function or method it matches, leaving the rest of the body intact.
+pub(crate) fn insert_statements<P: Pattern>(
+    pattern: &str,
+    code: &str,
+    patch: &Patch,
+) -> Result<String> {
+    let patch_parser = Parser::<P>::new(&patch.code);
+    let target = patch_parser
+        .find_first_match()
+        .ok_or_else(|| Error::PatternNotFound {
+            pattern: pattern.to_owned(),
+            code: patch.code.to_owned(),
+        })?;
+    let name = target.ident();
+    let stmts = patch_parser
+        .find_node(|pat| pat.is_match(&target))
+        .and_then(|func| func.child_by_field_name("body"))
+        .map(|body| body_text(body, &patch.code))
+        .unwrap_or_default();
+
+    let parser = Parser::<P>::new(code);
+    let func = parser
+        .find_node(|pat| pat.is_match(&target))
+        .ok_or_else(|| Error::SymbolNotFound {
+            pattern: pattern.to_owned(),
+            name: name.to_owned(),
+        })?;
+    let body = func
+        .child_by_field_name("body")
+        .ok_or_else(|| Error::SymbolNotFound {
+            pattern: pattern.to_owned(),
+            name: name.to_owned(),
+        })?;
+
+    let mut insertions = match patch.patch_type {
+        Some(PatchType::InsertAtStart) => vec![at_start(body, code)],
+        Some(PatchType::InsertBeforeReturn) => {
+            let mut returns = Vec::new();
+            descendants(body, &mut returns);
+            returns.retain(|node| node.kind() == "return_statement");
+
+            let mut insertions = returns
+                .into_iter()
+                .map(|ret| before(ret, code).unwrap_or_else(|| breaking(ret, code)))
+                .collect::<Vec<_>>();
+
+            // a function without results may also return by reaching the end of its body
+            let last = last_statement(body);
+            if func.child_by_field_name("result").is_none()
+                && last.map_or(true, |last| last.kind() != "return_statement")
+            {
+                insertions.push(at_end(body, code));
+            }
+            insertions
+        }
+        Some(PatchType::InsertAfter) => {
+            let anchor = patch.anchor.as_deref().unwrap_or_default();
+            let anchored = anchored(body, code, anchor).ok_or_else(|| Error::SymbolNotFound {
+                pattern: pattern.to_owned(),
+                name: format!("{} statement `{}`", name, anchor),
+            })?;
+            let at = after(anchored, code).ok_or_else(|| Error::SymbolNotFound {
+                pattern: pattern.to_owned(),
+                name: format!("{} statement `{}` on its own line", name, anchor),
+            })?;
+            vec![at]
+        }
+        _ => unreachable!("not a statement insertion"),
+    };
+
+    // insert from the bottom up, so that the positions stay valid
+    insertions.sort_by_key(|at| std::cmp::Reverse(at.pos));
+
+    let mut next = code.to_owned();
+    for at in insertions {
+        let text = at.render(&stmts);
+
+        // already inserted by a previous run
+        if next[at.pos..].starts_with(&text) || next[..at.pos].ends_with(&text) {
+            info!("{}: statements already in place", name);
+            continue;
+        }
+
+        // breaking a line; drop the spaces around the break
+        let mut range = at.pos..at.pos;
+        if at.inline.is_some() {
+            range.start = next[..at.pos].trim_end_matches([' ', '\t']).len();
+            range.end = next.len() - next[at.pos..].trim_start_matches([' ', '\t']).len();
+        }
+        next.replace_range(range, &text);
+    }
+    Ok(next)
+}
+
+// Insertion is a position to insert statements at, indented by indent
+struct Insertion {
+    pos: usize,
+    indent: String,
+
+    // the position is in the middle of a line, e.g. right after `{` in `func f() {}`
+    inline: Option<String>,
+}
+
+impl Insertion {
+    fn render(&self, stmts: &str) -> String {
+        let lines = stmts
+            .lines()
+            .map(|line| match line.is_empty() {
+                true => "\n".to_owned(),
+                false => format!("{}{}\n", self.indent, line),
+            })
+            .collect::<String>();
+
+        match &self.inline {
+            Some(outer) => format!("\n{}{}", lines, outer),
+            None => lines,
+        }
+    }
+}
+
+fn at_start(body: Node, code: &str) -> Insertion {
+    let open = body.child(0).unwrap();
+    let rest = &code[open.end_byte()..];
+    let line_end = rest.find('\n').map(|i| open.end_byte() + i + 1);
+    let indent = format!("{}\t", indent_at(code, body.start_byte()));
+
+    match (
+        line_end.filter(|end| code[open.end_byte()..*end].trim().is_empty()),
+        first_statement(body),
+    ) {
+        (Some(pos), first) => Insertion {
+            pos,
+            indent: first.map_or(indent, |first| indent_at(code, first.start_byte())),
+            inline: None,
+        },
+        // e.g. `func f() {}`
+        (None, None) => Insertion {
+            pos: open.end_byte(),
+            indent,
+            inline: Some(String::new()),
+        },
+        // e.g. `func f() int { return 1 }`; the first statement moves to a line of its own
+        (None, Some(_)) => Insertion {
+            pos: open.end_byte(),
+            indent: indent.to_owned(),
+            inline: Some(indent),
+        },
+    }
+}
+
+fn at_end(body: Node, code: &str) -> Insertion {
+    let close = body.child(body.child_count() - 1).unwrap();
+    let outer = indent_at(code, body.start_byte());
+    let indent = match last_statement(body) {
+        Some(last) => indent_at(code, last.start_byte()),
+        None => format!("{}\t", outer),
+    };
+
+    match before(close, code) {
+        Some(at) => Insertion { indent, ..at },
+        None => Insertion {
+            pos: close.start_byte(),
+            indent,
+            inline: Some(outer),
+        },
+    }
+}
+
+// before is the start of the line node is on, if nothing precedes node on it
+fn before(node: Node, code: &str) -> Option<Insertion> {
+    let line_start = code[..node.start_byte()].rfind('\n').map_or(0, |i| i + 1);
+    match code[line_start..node.start_byte()].trim().is_empty() {
+        true => Some(Insertion {
+            pos: line_start,
+            indent: indent_at(code, node.start_byte()),
+            inline: None,
+        }),
+        false => None,
+    }
+}
+
+// breaking is right before a node sharing its line, which moves to a line of its own;
+// e.g. `return 1` in `func f() int { return 1 }`
+fn breaking(node: Node, code: &str) -> Insertion {
+    let opens = node
+        .prev_sibling()
+        .map_or(false, |prev| matches!(prev.kind(), "{" | ":"));
+    let indent = match (opens, node.parent()) {
+        (true, Some(parent)) => format!("{}\t", indent_at(code, parent.start_byte())),
+        _ => indent_at(code, node.start_byte()),
+    };
+
+    Insertion {
+        pos: node.start_byte(),
+        indent: indent.to_owned(),
+        inline: Some(indent),
+    }
+}
+
+// after is the start of the line following node, if nothing but a comment follows node
+fn after(node: Node, code: &str) -> Option<Insertion> {
+    let rest = &code[node.end_byte()..];
+    let line_end = rest.find('\n')?;
+    let tail = rest[..line_end].trim();
+    match tail.is_empty() || tail.starts_with("//") {
+        true => Some(Insertion {
+            pos: node.end_byte() + line_end + 1,
+            indent: indent_at(code, node.start_byte()),
+            inline: None,
+        }),
+        false => None,
+    }
+}
+
+// anchored finds the innermost statement containing anchor, first in source order
+fn anchored<'tree>(body: Node<'tree>, code: &str, anchor: &str) -> Option<Node<'tree>> {
+    let mut stmts = Vec::new();
+    descendants(body, &mut stmts);
+    stmts.retain(|stmt| is_statement(*stmt) && code[stmt.byte_range()].contains(anchor));
+
+    stmts.iter().copied().find(|stmt| {
+        !stmts.iter().any(|inner| {
+            inner.id() != stmt.id()
+                && inner.start_byte() >= stmt.start_byte()
+                && inner.end_byte() <= stmt.end_byte()
+        })
+    })
+}
+
+// descendants collects every node below node in preorder, except for function literals
+fn descendants<'tree>(node: Node<'tree>, out: &mut Vec<Node<'tree>>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if child.kind() == "func_literal" {
+            continue;
+        }
+        out.push(child);
+        descendants(child, out);
+    }
+}
+
+fn is_statement(node: Node) -> bool {
+    node.kind() != "comment"
+        && node
+            .parent()
+            .map_or(false, |parent| STATEMENT_PARENTS.contains(&parent.kind()))
+}
+
+fn first_statement(body: Node) -> Option<Node> {
+    let mut cursor = body.walk();
+    let first = body
+        .named_children(&mut cursor)
+        .find(|child| child.kind() != "comment");
+    first
+}
+
+fn last_statement(body: Node) -> Option<Node> {
+    let mut cursor = body.walk();
+    let last = body
+        .named_children(&mut cursor)
+        .filter(|child| child.kind() != "comment")
+        .last();
+    last
+}
+
+// body_text returns the statements of a body, dedented to the body's own indentation
+fn body_text(body: Node, code: &str) -> String {
+    let mut cursor = body.walk();
+    let children = body.named_children(&mut cursor).collect::<Vec<_>>();
+    let (Some(first), Some(last)) = (children.first(), children.last()) else {
+        return String::new();
+    };
+
+    let line_start = code[..first.start_byte()].rfind('\n').map_or(0, |i| i + 1);
+    let base = match code[line_start..first.start_byte()].trim().is_empty() {
+        true => &code[line_start..first.start_byte()],
+        false => "",
+    };
+
+    code[first.start_byte()..last.end_byte()]
+        .lines()
+        .map(|line| line.strip_prefix(base).unwrap_or(line.trim_start()))
+        .map(|line| line.trim_end())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::insert_statements;
+    use crate::manifest::{Patch, PatchType};
+    use crate::patterns::func_decl::FunctionDeclPattern;
+    use crate::patterns::method_decl::MethodDeclPattern;
+
+    const CODE: &str = r#"package main
+
+func (s *Server) Handle(req Request) error {
+	if req.Invalid() {
+		return ErrInvalid
+	}
+	s.count++
+	defer func() error { return nil }()
+	return s.next(req)
+}
+
+func noop() {}
+"#;
+
+    fn patch(patch_type: PatchType, anchor: Option<&str>, code: &str) -> Patch {
+        Patch {
+            pattern: Some("method_declaration".to_owned()),
+            patch_type: Some(patch_type),
+            anchor: anchor.map(str::to_owned),
+            code: code.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_insert_before_return() {
+        let patch = patch(
+            PatchType::InsertBeforeReturn,
+            None,
+            "func (s *Server) Handle(req Request) error {\n\tmetrics.Observe(\"handle\")\n}",
+        );
+        let inserted =
+            insert_statements::<MethodDeclPattern>("method_declaration", CODE, &patch).unwrap();
+        assert_eq!(
+            inserted,
+            CODE.replace(
+                "\t\treturn ErrInvalid",
+                "\t\tmetrics.Observe(\"handle\")\n\t\treturn ErrInvalid"
+            )
+            .replace(
+                "\treturn s.next",
+                "\tmetrics.Observe(\"handle\")\n\treturn s.next"
+            )
+        );
+
+        // inserting twice is a no-op
+        let again = insert_statements::<MethodDeclPattern>("method_declaration", &inserted, &patch);
+        assert_eq!(again.unwrap(), inserted);
+
+        // a return sharing its line is broken up
+        let code = "package main\n\nfunc one() int { return 1 }\n";
+        let one = Patch {
+            pattern: Some("function_declaration".to_owned()),
+            code: "func one() int { trace() }".to_owned(),
+            ..patch.clone()
+        };
+        let inserted =
+            insert_statements::<FunctionDeclPattern>("function_declaration", code, &one).unwrap();
+        assert_eq!(
+            inserted,
+            "package main\n\nfunc one() int {\n\ttrace()\n\treturn 1 }\n"
+        );
+        let again =
+            insert_statements::<FunctionDeclPattern>("function_declaration", &inserted, &one);
+        assert_eq!(again.unwrap(), inserted);
+
+        // falling off the end of a function counts as returning
+        let patch = Patch {
+            pattern: Some("function_declaration".to_owned()),
+            code: "func noop() {\n\tif debug {\n\t\tlog()\n\t}\n}".to_owned(),
+            ..patch
+        };
+        let inserted =
+            insert_statements::<FunctionDeclPattern>("function_declaration", CODE, &patch);
+        assert!(inserted
+            .unwrap()
+            .contains("func noop() {\n\tif debug {\n\t\tlog()\n\t}\n}"));
+    }
+
+    #[test]
+    fn test_insert_at_start_and_after() {
+        let patch = patch(
+            PatchType::InsertAtStart,
+            None,
+            "func (s *Server) Handle(req Request) error { s.mu.Lock(); defer s.mu.Unlock() }",
+        );
+        let inserted = insert_statements::<MethodDeclPattern>("method_declaration", CODE, &patch);
+        assert!(inserted
+            .unwrap()
+            .contains("error {\n\ts.mu.Lock(); defer s.mu.Unlock()\n\tif req.Invalid()"));
+
+        // a one-line body is broken up
+        let code = "package main\n\nfunc one() int { return 1 }\n";
+        let start = Patch {
+            pattern: Some("function_declaration".to_owned()),
+            code: "func one() int { trace() }".to_owned(),
+            ..patch.clone()
+        };
+        let inserted =
+            insert_statements::<FunctionDeclPattern>("function_declaration", code, &start).unwrap();
+        assert_eq!(
+            inserted,
+            "package main\n\nfunc one() int {\n\ttrace()\n\treturn 1 }\n"
+        );
+        let again =
+            insert_statements::<FunctionDeclPattern>("function_declaration", &inserted, &start);
+        assert_eq!(again.unwrap(), inserted);
+
+        let patch = Patch {
+            patch_type: Some(PatchType::InsertAfter),
+            anchor: Some("s.count++".to_owned()),
+            code: "func (s *Server) Handle(req Request) error {\n\tlog.Println(s.count)\n}"
+                .to_owned(),
+            ..patch
+        };
+        let inserted = insert_statements::<MethodDeclPattern>("method_declaration", CODE, &patch);
+        assert!(inserted
+            .unwrap()
+            .contains("\ts.count++\n\tlog.Println(s.count)\n\tdefer"));
+
+        let patch = Patch {
+            anchor: Some("s.missing()".to_owned()),
+            ..patch
+        };
+        assert!(
+            insert_statements::<MethodDeclPattern>("method_declaration", CODE, &patch).is_err()
+        );
+    }
+}
@@ -1,13 +1,19 @@
-use crate::patterns::Pattern;
+use crate::manifest::PatchType;
+use crate::patterns::members::edit_members;
+use crate::patterns::{delete_type_spec, Pattern};
+use crate::Result;
 use tree_sitter::QueryMatch;
 
 pub struct StructDeclPattern {
     pub name: String,
+    pub type_params: String,
     pub fields: Vec<String>,
     pub struct_itself: String,
 }
 
 impl Pattern for StructDeclPattern {
+    const KIND: &'static str = "struct_decl";
+
     fn ident(&self) -> String {
         self.name.to_owned()
     }
@@ -18,6 +24,7 @@ impl Pattern for StructDeclPattern {
 	(type_declaration
     	(type_spec
         	name: (type_identifier) @name
+            type_parameters: (type_parameter_list)? @type_params
             type: (struct_type
             	(field_declaration_list
                 	(field_declaration
@@ -33,7 +40,19 @@ impl Pattern for StructDeclPattern {
     fn from_match(matched: &QueryMatch, code: &str) -> Self {
         let struct_itself = &code[matched.captures[0].node.byte_range()];
         let struct_name = &code[matched.captures[1].node.byte_range()];
-        let fields = match matched.captures.get(2) {
+        let type_params = match matched
+            .captures
+            .iter()
+            .find(|cap| cap.node.kind() == "type_parameter_list")
+        {
+            Some(cap) => &code[cap.node.byte_range()],
+            None => "",
+        };
+        let fields = match matched
+            .captures
+            .iter()
+            .find(|cap| cap.node.kind() == "field_identifier")
+        {
             Some(cap) => &code[cap.node.byte_range()],
             None => "",
         };
@@ -41,31 +60,145 @@ impl Pattern for StructDeclPattern {
         Self {
             struct_itself: struct_itself.to_owned(),
             name: struct_name.to_owned(),
+            type_params: type_params.to_owned(),
             fields: vec![fields.to_owned()],
         }
     }
 
-    fn append_suffix(matched: &QueryMatch, codebuf: &str) -> String {
+    fn append_suffix(matched: &QueryMatch, codebuf: &str, rename_to: &str) -> String {
         let struct_name_capture = matched.captures[1];
         let struct_name = &codebuf[struct_name_capture.node.byte_range()];
 
         let mut next = codebuf.to_owned();
         next.replace_range(
             struct_name_capture.node.byte_range(),
-            &format!("{}_{}", struct_name, "_replaced_by_struct_decl"),
+            &Self::renamed(struct_name, rename_to),
         );
         next
     }
 
+    // removes the whole declaration, or only the spec inside a type ( ... ) group
     fn delete(matched: &QueryMatch, codebuf: &str) -> String {
-        let struct_capture = matched.captures[0];
-
-        let mut next = codebuf.to_owned();
-        next.replace_range(struct_capture.node.byte_range(), "");
-        next
+        let spec = matched.captures[1].node.parent().unwrap();
+        delete_type_spec(codebuf, spec)
     }
 
     fn is_match(&self, other: &Self) -> bool {
         self.name == other.name
     }
 }
+
+// edit_fields adds, removes or retypes individual fields of a struct in place.
+// the patch is a struct declaration holding only the fields to edit.
+pub fn edit_fields(code: &str, patch: &str, patch_type: &PatchType) -> Result<String> {
+    edit_members::<StructDeclPattern>("struct_declaration", code, patch, patch_type, |ty| {
+        let mut cursor = ty.walk();
+        let list = ty
+            .named_children(&mut cursor)
+            .find(|child| child.kind() == "field_declaration_list");
+        list
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edit_fields, StructDeclPattern};
+    use crate::manifest::PatchType;
+    use crate::patch::parser::Parser;
+    use crate::patterns::interface_decl::InterfaceDeclPattern;
+    use crate::patterns::{Pattern, DEFAULT_RENAME_TO};
+
+    const CODE: &str = r#"package main
+
+type Config struct {
+	io.Reader
+	Name, Alias string `json:"name"` // display name
+	Port        int
+}
+"#;
+
+    #[test]
+    fn test_add_and_remove_field() {
+        let patch =
+            "type Config struct {\n\tTimeout time.Duration `json:\"timeout\"`\n\t*Logger\n}";
+        let added = edit_fields(CODE, patch, &PatchType::AddField).unwrap();
+        assert!(added.contains(
+            "\tPort        int\n\tTimeout time.Duration `json:\"timeout\"`\n\t*Logger\n}"
+        ));
+
+        // adding twice is a no-op
+        assert_eq!(
+            edit_fields(&added, patch, &PatchType::AddField).unwrap(),
+            added
+        );
+
+        let patch = "type Config struct {\n\tAlias string\n\tReader\n}";
+        let removed = edit_fields(CODE, patch, &PatchType::RemoveField).unwrap();
+        assert_eq!(
+            removed,
+            r#"package main
+
+type Config struct {
+	Name string `json:"name"` // display name
+	Port        int
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_replace_field() {
+        let patch = "type Config struct {\n\tPort uint16\n\tAlias []string\n}";
+        let replaced = edit_fields(CODE, patch, &PatchType::ReplaceField).unwrap();
+        assert_eq!(
+            replaced,
+            r#"package main
+
+type Config struct {
+	io.Reader
+	Name string `json:"name"`
+	Alias []string // display name
+	Port uint16
+}
+"#
+        );
+
+        let patch = "type Config struct {\n\tMissing int\n}";
+        assert!(edit_fields(CODE, patch, &PatchType::ReplaceField).is_err());
+    }
+
+    #[test]
+    fn test_generic_struct() {
+        let source =
+            "package main\n\ntype Pair[K comparable, V any] struct {\n\tKey   K\n\tValue V\n}\n";
+        let patch =
+            "type Pair[K comparable, V any] struct {\n\tKey   K\n\tValue V\n\tValid bool\n}";
+        let target = Parser::<StructDeclPattern>::new(patch)
+            .find_first_match()
+            .unwrap();
+        assert_eq!(target.name, "Pair");
+        assert_eq!(target.type_params, "[K comparable, V any]");
+
+        let result = Parser::<StructDeclPattern>::new(source)
+            .find_and_patch(|s| s.is_match(&target), DEFAULT_RENAME_TO);
+        let renamed = source.replace("type Pair[", "type Pair__replaced_by_struct_decl[");
+        assert_eq!(result, Some(renamed));
+
+        let patch = "type Pair[K comparable, V any] struct {\n\tValid bool\n}";
+        let added = edit_fields(source, patch, &PatchType::AddField).unwrap();
+        assert!(added.contains("\tValue V\n\tValid bool\n}"));
+    }
+
+    #[test]
+    fn test_delete_in_group() {
+        let source =
+            "package main\n\ntype (\n\tA struct {\n\t\tx int\n\t}\n\tB interface{ Get() }\n)\n";
+        let deleted = Parser::<StructDeclPattern>::new(source).find_and_delete(|s| s.name == "A");
+        let expected = "package main\n\ntype (\n\tB interface{ Get() }\n)\n";
+        assert_eq!(deleted.unwrap(), expected);
+
+        let deleted =
+            Parser::<InterfaceDeclPattern>::new(expected).find_and_delete(|i| i.name == "B");
+        assert_eq!(deleted.unwrap(), "package main\n\n\n");
+    }
+}
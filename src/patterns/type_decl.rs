@@ -0,0 +1,105 @@
+use crate::patterns::{delete_type_spec, Pattern};
+use tree_sitter::QueryMatch;
+
+// matches any named type regardless of its underlying type,
+// including aliases (`type Coin = sdk.Coin`) and grouped type ( ... ) declarations
+const S_EXP: &str = r#"
+(source_file
+    (type_declaration
+        [
+            (type_spec name: (type_identifier) @name)
+            (type_alias name: (type_identifier) @name)
+        ] @spec
+    ) @type_decl
+)"#;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct TypeDeclPattern {
+    pub name: String,
+    pub spec: String,
+    pub type_itself: String,
+}
+
+impl Pattern for TypeDeclPattern {
+    const KIND: &'static str = "type_decl";
+
+    fn ident(&self) -> String {
+        self.name.to_owned()
+    }
+
+    fn sexp() -> &'static str {
+        S_EXP
+    }
+
+    fn from_match(matched: &QueryMatch, code: &str) -> Self {
+        let type_itself = &code[matched.captures[0].node.byte_range()];
+        let spec = &code[matched.captures[1].node.byte_range()];
+        let name = &code[matched.captures[2].node.byte_range()];
+
+        Self {
+            name: name.to_owned(),
+            spec: spec.to_owned(),
+            type_itself: type_itself.to_owned(),
+        }
+    }
+
+    fn append_suffix(matched: &QueryMatch, codebuf: &str, rename_to: &str) -> String {
+        let name_capture = matched.captures[2];
+        let name = &codebuf[name_capture.node.byte_range()];
+
+        let mut next = codebuf.to_owned();
+        next.replace_range(
+            name_capture.node.byte_range(),
+            &Self::renamed(name, rename_to),
+        );
+        next
+    }
+
+    // removes the whole declaration, or only the spec's line inside a type ( ... ) group
+    fn delete(matched: &QueryMatch, codebuf: &str) -> String {
+        delete_type_spec(codebuf, matched.captures[1].node)
+    }
+
+    fn is_match(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TypeDeclPattern;
+    use crate::patch::parser::Parser;
+    use crate::patterns::DEFAULT_RENAME_TO;
+
+    const SOURCE: &str = r#"package main
+
+type Coin = sdk.Coin
+
+type (
+	Handler func(ctx Context) error
+	IDs     []uint64
+)
+"#;
+
+    #[test]
+    fn test_find_and_patch() {
+        let parser = Parser::<TypeDeclPattern>::new(SOURCE);
+
+        let result = parser.find_and_patch(|t| t.name == "Coin", DEFAULT_RENAME_TO);
+        let expected = SOURCE.replace("type Coin =", "type Coin__replaced_by_type_decl =");
+        assert_eq!(result, Some(expected));
+
+        let result = parser.find_and_patch(|t| t.name == "IDs", DEFAULT_RENAME_TO);
+        let expected = SOURCE.replace("IDs     []", "IDs__replaced_by_type_decl     []");
+        assert_eq!(result, Some(expected));
+    }
+
+    #[test]
+    fn test_find_and_delete_in_group() {
+        let result =
+            Parser::<TypeDeclPattern>::new(SOURCE).find_and_delete(|t| t.name == "Handler");
+
+        let expected = SOURCE.replace("\tHandler func(ctx Context) error\n", "");
+        assert_eq!(result, Some(expected));
+    }
+}
@@ -1,65 +1,107 @@
-use crate::patterns::Pattern;
+use crate::patterns::{delete_name, Pattern};
 use tree_sitter::QueryMatch;
 
 pub struct VariableDeclPattern {
     pub var_name: String,
     pub var_type: String,
+    pub var_spec: String,
     pub var_itself: String,
 }
 
 impl Pattern for VariableDeclPattern {
+    const KIND: &'static str = "var_decl";
+
     fn ident(&self) -> String {
         self.var_name.to_owned()
     }
 
+    // identifiers are matched without the `name:` field, so that every name of
+    // a multi-name spec (`var x, y int`) yields its own match
     fn sexp() -> &'static str {
         r#"
 (source_file
 	(var_declaration
     	(var_spec
-        	name: (identifier) @name
-            type: (type_identifier)? @type
-        )
+        	((identifier) @name)
+        ) @var_spec
     ) @var_decl
 )"#
     }
 
     fn from_match(matched: &QueryMatch, code: &str) -> Self {
         let var_itself = &code[matched.captures[0].node.byte_range()];
-        let var_name = &code[matched.captures[1].node.byte_range()];
-        let var_type = match matched.captures.get(2) {
-            Some(cap) => &code[cap.node.byte_range()],
+        let spec_node = matched.captures[1].node;
+        let var_spec = &code[spec_node.byte_range()];
+        let var_name = &code[matched.captures[2].node.byte_range()];
+        let var_type = match spec_node.child_by_field_name("type") {
+            Some(node) => &code[node.byte_range()],
             None => "",
         };
 
         Self {
             var_itself: var_itself.to_owned(),
+            var_spec: var_spec.to_owned(),
             var_name: var_name.to_owned(),
             var_type: var_type.to_owned(),
         }
     }
 
-    fn append_suffix(matched: &QueryMatch, codebuf: &str) -> String {
-        let struct_name_capture = matched.captures[1];
-        let struct_name = &codebuf[struct_name_capture.node.byte_range()];
+    fn append_suffix(matched: &QueryMatch, codebuf: &str, rename_to: &str) -> String {
+        let var_name_capture = matched.captures[2];
+        let var_name = &codebuf[var_name_capture.node.byte_range()];
 
         let mut next = codebuf.to_owned();
         next.replace_range(
-            struct_name_capture.node.byte_range(),
-            &format!("{}_{}", struct_name, "_replaced_by_var_decl"),
+            var_name_capture.node.byte_range(),
+            &Self::renamed(var_name, rename_to),
         );
         next
     }
 
+    // only the matched name goes away, leaving the rest of a var ( ... ) group alone
     fn delete(matched: &QueryMatch, codebuf: &str) -> String {
-        let var_capture = matched.captures[0];
-
-        let mut next = codebuf.to_owned();
-        next.replace_range(var_capture.node.byte_range(), "");
-        next
+        let [decl, spec, name] = [0, 1, 2].map(|i| matched.captures[i].node);
+        delete_name(decl, spec, name, codebuf, false)
     }
 
     fn is_match(&self, other: &Self) -> bool {
         self.var_name == other.var_name
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::VariableDeclPattern;
+    use crate::patch::parser::Parser;
+    use crate::patterns::DEFAULT_RENAME_TO;
+
+    const SOURCE: &str = r#"package main
+
+var (
+	a = 1
+	b = 2
+)
+
+var x, y int
+"#;
+
+    #[test]
+    fn test_find_and_delete_in_group() {
+        let parser = Parser::<VariableDeclPattern>::new(SOURCE);
+
+        let result = parser.find_and_delete(|v| v.var_name == "b");
+        assert_eq!(result, Some(SOURCE.replace("\tb = 2\n", "")));
+
+        let result = parser.find_and_delete(|v| v.var_name == "y");
+        assert_eq!(result, Some(SOURCE.replace("x, y int", "x, _ int")));
+    }
+
+    #[test]
+    fn test_find_and_patch_multi_name() {
+        let result = Parser::<VariableDeclPattern>::new(SOURCE)
+            .find_and_patch(|v| v.var_name == "y", DEFAULT_RENAME_TO);
+
+        let expected = SOURCE.replace("x, y int", "x, y__replaced_by_var_decl int");
+        assert_eq!(result, Some(expected));
+    }
+}
@@ -1,20 +1,80 @@
 use crate::fs_buffer::FsBuffer;
-use crate::manifest::Manifest;
-use crate::try_patch;
+use crate::manifest::{Manifest, ManifestFile, ManifestImport, Patch, VersionPolicy};
+use crate::modules::{satisfies, Modules};
+use crate::patterns::import_decl::{merge_imports, prune_imports};
+use crate::patterns::{declares, DEFAULT_RENAME_TO};
+use crate::state;
+use crate::{try_patch, Error, Result};
 use log::{error, info};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 #[derive(clap::Parser, Debug)]
 #[command()]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum Command {
+    /// apply patch manifests to the target directory
+    Apply(ApplyArgs),
+
+    /// fail if the target directory is not in patched state
+    Check(Args),
+
+    /// restore the files recorded by previous `apply` runs
+    Revert(RevertArgs),
+}
+
+#[derive(clap::Args, Debug)]
 pub struct Args {
     #[arg(short, long, default_value = "vendor")]
     pub dir: String,
 
+    /// directory modules are copied into from the module cache, when there is no `dir`
+    #[arg(long, default_value = ".govld_modules")]
+    pub modules_dir: String,
+
     pub patch_manifest_files: Vec<String>,
 }
 
-pub fn do_run(cwd: impl AsRef<Path>, args: Args) {
+#[derive(clap::Args, Debug)]
+pub struct ApplyArgs {
+    #[command(flatten)]
+    pub args: Args,
+
+    /// print a unified diff of the patched files instead of writing them
+    #[arg(long, visible_alias = "diff")]
+    pub dry_run: bool,
+
+    /// directory where the pre-patch content of every written file is recorded
+    #[arg(long, default_value = ".govld")]
+    pub state_dir: String,
+
+    /// write the patched files into this directory along with an overlay.json
+    /// for `go build -overlay`, leaving the files in place untouched
+    #[arg(long, conflicts_with = "dry_run")]
+    pub overlay: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct RevertArgs {
+    #[arg(short, long, default_value = "vendor")]
+    pub dir: String,
+
+    /// directory modules were copied into from the module cache, when there is no `dir`
+    #[arg(long, default_value = ".govld_modules")]
+    pub modules_dir: String,
+
+    /// directory the pre-patch content was recorded into by `apply`
+    #[arg(long, default_value = ".govld")]
+    pub state_dir: String,
+}
+
+pub fn do_run(cwd: impl AsRef<Path>, cli: Cli) -> ExitCode {
     // force info level
     let mut builder = env_logger::builder();
     let mut builder = &mut builder;
@@ -23,167 +83,657 @@ pub fn do_run(cwd: impl AsRef<Path>, args: Args) {
     }
     builder.init();
 
-    let dir = cwd.as_ref().join(args.dir);
-    info!("dir: {}", dir.display());
+    let result = match cli.command {
+        Command::Apply(ApplyArgs {
+            args,
+            dry_run,
+            state_dir,
+            overlay,
+        }) => {
+            let state_dir = cwd.as_ref().join(state_dir);
+            let output = match (dry_run, overlay) {
+                (true, _) => Output::Diff,
+                (false, Some(overlay)) => Output::Overlay(cwd.as_ref().join(overlay)),
+                (false, None) => Output::InPlace(state_dir),
+            };
 
-    run(cwd, dir, args.patch_manifest_files)
-}
+            detect(cwd.as_ref(), &args.dir, &args.modules_dir).and_then(|modules| {
+                run(cwd, &modules, output, args.patch_manifest_files).map(|_| true)
+            })
+        }
+        Command::Check(args) => detect(cwd.as_ref(), &args.dir, &args.modules_dir)
+            .and_then(|modules| check(cwd, &modules, args.patch_manifest_files)),
+        Command::Revert(RevertArgs {
+            dir,
+            modules_dir,
+            state_dir,
+        }) => {
+            let state_dir = cwd.as_ref().join(state_dir);
 
-pub fn run(cwd: impl AsRef<Path>, dir: PathBuf, patch_manifest_files: Vec<String>) {
-    // organise patch files
-    let patch_manifest_files = patch_manifest_files
-        .iter()
-        .map(|p| {
-            let path = PathBuf::new().join(&cwd).join(p.clone());
-            path.exists().then(|| path.clone()).unwrap_or_else(|| {
-                error!(
-                    "error getting patch manifest file: {}",
-                    path.to_str().unwrap()
-                );
-                std::process::exit(127);
+            detect(cwd.as_ref(), &dir, &modules_dir).and_then(|modules| {
+                state::revert(&state_dir, modules.root()).map(|restored| {
+                    info!("reverted {} file(s)", restored.len());
+                    true
+                })
             })
-        })
-        .collect::<Vec<_>>();
+        }
+    };
 
-    info!("patch manifest files: {:?}", &patch_manifest_files);
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            error!("{}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
 
+// detect locates the files to patch: the vendor directory, or modules_dir for modules
+// copied from the module cache
+fn detect(cwd: &Path, dir: &str, modules_dir: &str) -> Result<Modules> {
+    let modules = Modules::detect(cwd, &cwd.join(dir), &cwd.join(modules_dir))?;
+    info!("dir: {}", modules.root().display());
+    Ok(modules)
+}
+
+/// Output is where `run` puts the patched files
+pub enum Output {
+    /// write them in place, recording the pre-patch content into the state directory
+    InPlace(PathBuf),
+
+    /// only print a unified diff of them
+    Diff,
+
+    /// write them into a directory, along with the overlay.json `go build -overlay` takes
+    Overlay(PathBuf),
+}
+
+pub fn run(
+    cwd: impl AsRef<Path>,
+    modules: &Modules,
+    output: Output,
+    patch_manifest_files: Vec<String>,
+) -> Result<()> {
     // for each patch manifest file, try to patch
     // define code buf cache to avoid re-reading the same file
-    let fsb = &mut FsBuffer::new(&dir);
+    // only patching in place copies modules over; the rest reads them where they are
+    let in_place = matches!(output, Output::InPlace(_));
+    let fsb = &mut match in_place {
+        true => FsBuffer::new(modules.root()),
+        false => FsBuffer::new(modules.root()).with_dirs(modules.dirs()),
+    };
+    patch(cwd, modules, fsb, in_place, patch_manifest_files)?;
+
+    match output {
+        // in dry-run mode, only show what would have been written
+        Output::Diff => {
+            print!("{}", fsb.diff());
+            Ok(())
+        }
+        Output::Overlay(overlay_dir) => {
+            let json_path = fsb.flush_overlay(&overlay_dir)?;
+            info!("build with: go build -overlay={}", json_path.display());
+            Ok(())
+        }
+        Output::InPlace(state_dir) => {
+            // record the pre-patch content, so that it can be reverted
+            for path in fsb.modified() {
+                let original = fsb.original(path).unwrap_or_default();
+                let patched = fsb.patched(path).unwrap_or_default();
+                state::record(&state_dir, path, original, patched)?;
+            }
 
-    // patches is a global buffer for all patches to be made
-    let patches: HashMap<String, Vec<String>> = HashMap::new();
-    let imports: HashMap<String, Vec<String>> = HashMap::new();
-    let safe_ranges: HashMap<String, std::ops::Range<usize>> = HashMap::new();
+            // actually write to file
+            fsb.flush()
+        }
+    }
+}
+
+/// check patches everything in memory and reports every file that would change,
+/// along with the symbols patched in it. returns true if everything is up to date.
+pub fn check(
+    cwd: impl AsRef<Path>,
+    modules: &Modules,
+    patch_manifest_files: Vec<String>,
+) -> Result<bool> {
+    let fsb = &mut FsBuffer::new(modules.root()).with_dirs(modules.dirs());
+    let symbols = patch(cwd, modules, fsb, false, patch_manifest_files)?;
+
+    let modified = fsb.modified();
+    for path in &modified {
+        let symbols = symbols.get(*path).cloned().unwrap_or_default();
+        error!("out of date: {} [{}]", path, symbols.join(", "));
+    }
+
+    Ok(modified.is_empty())
+}
+
+// Collected buffers everything gathered from the manifests, keyed by file,
+// until every manifest has been processed.
+#[derive(Default)]
+struct Collected {
+    patches: HashMap<String, Vec<(String, String)>>,
+    imports: HashMap<String, Vec<ManifestImport>>,
+    prune_imports: HashSet<String>,
+    symbols: HashMap<String, Vec<String>>,
+}
+
+/// patch applies every manifest to fsb without writing anything to disk,
+/// but for the modules materialized into place along the way, if asked to.
+/// returns the symbols patched, grouped by file.
+pub fn patch(
+    cwd: impl AsRef<Path>,
+    modules: &Modules,
+    fsb: &mut FsBuffer,
+    materialize: bool,
+    patch_manifest_files: Vec<String>,
+) -> Result<HashMap<String, Vec<String>>> {
+    info!("patch manifest files: {:?}", &patch_manifest_files);
+
+    // read every manifest upfront, so that a broken one fails before anything is patched
+    let manifests = patch_manifest_files
+        .iter()
+        .map(|p| {
+            let path = PathBuf::new().join(&cwd).join(p);
+            Ok(Manifest::from_path(path)?.with_source(p))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut collected = Collected::default();
 
     // iterate over all manifest files, try patch
-    let (fsb, patches, imports, safe_ranges) = patch_manifest_files.iter().fold(
-        (fsb, patches, imports, safe_ranges),
-        |(fsb, mut patches, mut imports, mut safe_ranges), path| {
-            // read manifest
-            let manifest_path = std::fs::read_to_string(path)
-                .unwrap_or_else(|_| panic!("error opening file: {}", &path.to_str().unwrap()));
-            let manifest: Manifest =
-                serde_yaml::from_str(manifest_path.as_str()).unwrap_or_else(|e| {
-                    panic!(
-                        "error parsing manifest file: {} at {}",
-                        e,
-                        &path.to_str().unwrap()
-                    );
-                });
-
-            info!("processing {}", &manifest.file);
-
-            // load code from fsb (loads from file if this is the first occurrence)
-            match fsb.try_load(manifest.file.to_owned()) {
-                // handle if patch target file is not found
-                None => {
-                    let is_optional = manifest.optional.unwrap_or(false);
-
-                    if is_optional {
-                        info!("skipping optional file: {}", &manifest.file);
-
-                        // short circuit here
-                        (fsb, patches, imports, safe_ranges)
-                    } else {
-                        panic!("error loading file: {}", &manifest.file);
-                    }
-                }
-                Some(code) => {
-                    // try patching
-                    let result = try_patch(code, &manifest);
-
-                    // update code (with __replaced__ modifications)
-                    fsb.update(&manifest.file, &result.code);
-
-                    // update imports
-                    imports
-                        .entry(manifest.file.to_owned())
-                        .or_default()
-                        .push(result.imports.join("\n"));
-
-                    // update patches
-                    patches
-                        .entry(manifest.file.to_owned())
-                        .or_default()
-                        .push(result.patches.join("\n"));
-
-                    // update safe_ranges (for imports)
-                    safe_ranges
-                        .entry(manifest.file)
-                        .or_insert(result.safe_range);
-
-                    // try post processing
-                    if manifest.postprocess.is_some() {
-                        for post in manifest.postprocess.unwrap() {
-                            let is_optional = post.optional.unwrap_or(false);
-                            info!("\tpostprocessing {}", &post.file);
-
-                            match fsb.try_load(post.file.to_owned()) {
-                                None => {
-                                    if !is_optional {
-                                        panic!("error loading postprocess file: {}", &post.file);
-                                    } else {
-                                        info!("skipping optional file: {}", &post.file);
-                                    }
-                                }
-                                Some(f) => {
-                                    let result = try_patch(f, &post);
-
-                                    // update code (with __replaced__ modifications)
-                                    fsb.update(&post.file, &result.code);
-
-                                    // update imports
-                                    imports
-                                        .entry(post.file.to_owned())
-                                        .or_default()
-                                        .push(result.imports.join("\n"));
-
-                                    // update patches
-                                    patches
-                                        .entry(post.file.to_owned())
-                                        .or_default()
-                                        .push(result.patches.join("\n"));
-
-                                    // update safe_ranges (for imports)
-                                    safe_ranges.entry(post.file).or_insert(result.safe_range);
-                                }
-                            }
-                        }
-                    }
-
-                    // fold over...
-                    (fsb, patches, imports, safe_ranges)
+    for manifest in &manifests {
+        info!("processing {}", manifest.target());
+
+        // postprocess only runs if the manifest itself was applied
+        if !patch_manifest(modules, fsb, materialize, manifest, &mut collected)? {
+            continue;
+        }
+
+        for post in manifest.postprocess.iter().flatten() {
+            info!("\tpostprocessing {}", post.target());
+            patch_manifest(modules, fsb, materialize, post, &mut collected)?;
+        }
+    }
+
+    // merge imports first
+    // missing imports are added into the existing import ( ... ) section,
+    // or into a new one right after the "package ..." declaration
+    for (path, imports_collected) in collected.imports {
+        let code = fsb.load(path.to_owned())?;
+        fsb.update(&path, &merge_imports(&path, &code, &imports_collected)?);
+    }
+
+    // apply patches to fsb
+    for (path, patches) in collected.patches {
+        for (id, patch) in patches {
+            fsb.append_patch(&path, &id, &patch)?;
+        }
+    }
+
+    // prune imports left unused by the patches, now that everything is in place
+    for path in collected.prune_imports {
+        let code = fsb.load(path.to_owned())?;
+        let original = fsb.original(&path).unwrap_or_default();
+        fsb.update(&path, &prune_imports(original, &code));
+    }
+
+    Ok(collected.symbols)
+}
+
+// patch_manifest patches every file matched by a manifest into fsb, collecting their patches
+// and imports. returns false if the manifest is optional and matched no file,
+// or if it is skipped for the version of its module.
+fn patch_manifest(
+    modules: &Modules,
+    fsb: &mut FsBuffer,
+    materialize: bool,
+    manifest: &Manifest,
+    collected: &mut Collected,
+) -> Result<bool> {
+    if !in_range(modules, manifest)? {
+        return Ok(false);
+    }
+
+    // bring the modules targeted into place before looking for any file
+    if materialize {
+        match &manifest.package {
+            Some(package) => modules.materialize(package)?,
+            None => {
+                for pattern in manifest.file.patterns() {
+                    modules.materialize(pattern)?;
                 }
             }
-        },
-    );
+        }
+    }
+
+    let optional = manifest.optional.unwrap_or(false);
+    let min_matches = manifest.min_matches.unwrap_or(if optional { 0 } else { 1 });
+    let paths = match &manifest.package {
+        Some(package) => package_files(fsb, package),
+        None => fsb.expand(&manifest.file.patterns()),
+    };
+
+    // handle if patch target file is not found
+    if paths.is_empty() && optional {
+        info!("skipping optional file: {}", manifest.target());
+        return Ok(false);
+    }
+    if paths.is_empty() && min_matches > 0 {
+        return Err(Error::TargetNotFound(manifest.target()));
+    }
+    if paths.len() < min_matches {
+        return Err(Error::TooFewMatches {
+            file: manifest.target(),
+            matches: paths.len(),
+            min_matches,
+        });
+    }
+
+    match manifest.package {
+        Some(_) => {
+            for (path, patches) in locate(fsb, manifest, &paths)? {
+                let manifest = Manifest {
+                    patch: patches,
+                    ..manifest.clone()
+                };
+                patch_file(fsb, &manifest, &path, collected)?;
+            }
+        }
+        None => {
+            for path in paths {
+                patch_file(fsb, manifest, &path, collected)?;
+            }
+        }
+    }
+
+    Ok(true)
+}
 
-    // apply imports first
-    // imports append import ( ... ) section at the top of the file
-    // but after the "package ..." declaration, using safe_range
-    for (path, imports_collected) in imports {
-        let import_statements = [
-            "import (",
-            format!("\t{}", imports_collected.join("\n")).as_str(),
-            ")",
-        ]
-        .join("\n");
+// in_range checks the version a manifest requires of its module, if any.
+// returns false if the manifest is to be skipped.
+fn in_range(modules: &Modules, manifest: &Manifest) -> Result<bool> {
+    let Some(range) = &manifest.version else {
+        return Ok(true);
+    };
 
-        let safe_range = safe_ranges
-            .get(&path)
-            .unwrap_or_else(|| panic!("error getting safe range for path {}", &path));
+    // the module providing the first file, unless named
+    let patterns = manifest.file.patterns();
+    let path = manifest
+        .module
+        .as_deref()
+        .or(manifest.package.as_deref())
+        .or(patterns.first().copied())
+        .unwrap_or_default();
+    let Some(module) = modules.find(path) else {
+        if manifest.optional.unwrap_or(false) {
+            info!("skipping optional manifest: no module provides {}", path);
+            return Ok(false);
+        }
+        return Err(Error::ModuleNotFound(path.to_owned()));
+    };
 
-        fsb.apply_patch_at(&path, &import_statements, safe_range);
+    if satisfies(&module.version, range) {
+        return Ok(true);
     }
+    match manifest.version_policy.unwrap_or_default() {
+        VersionPolicy::Skip => {
+            info!(
+                "skipping {}: {} {} does not satisfy {}",
+                manifest.target(),
+                module.path,
+                module.version,
+                range
+            );
+            Ok(false)
+        }
+        VersionPolicy::Fail => Err(Error::VersionMismatch {
+            module: module.path.to_owned(),
+            version: module.version.to_owned(),
+            required: range.to_string(),
+        }),
+    }
+}
 
-    // apply patches to fsb
-    for (path, patches) in patches {
-        for patch in patches {
-            fsb.append_patch(&path, &patch);
+// package_files lists the Go files of a package, sorted, leaving out tests
+fn package_files(fsb: &FsBuffer, package: &str) -> Vec<String> {
+    let pattern = format!("{}/*.go", package.trim_end_matches('/'));
+    let mut files = fsb
+        .expand(&[pattern.as_str()])
+        .into_iter()
+        .filter(|file| !file.ends_with("_test.go"))
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+// locate groups the patches of a package manifest by the file declaring what they patch.
+// patches declared nowhere, e.g. new declarations, go to the first file any patch was
+// located in, or to the first file of the package.
+fn locate(
+    fsb: &mut FsBuffer,
+    manifest: &Manifest,
+    files: &[String],
+) -> Result<Vec<(String, Vec<Patch>)>> {
+    // an empty package, allowed by min_matches: 0
+    if files.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut located: Vec<(Option<String>, &Patch)> = Vec::new();
+    for patch in &manifest.patch {
+        let rename_to = patch
+            .rename_to
+            .as_deref()
+            .or(manifest.rename_to.as_deref())
+            .unwrap_or(DEFAULT_RENAME_TO);
+
+        let mut found = None;
+        for file in files {
+            if declares(&fsb.load(file.to_owned())?, patch, rename_to)? {
+                found = Some(file.to_owned());
+                break;
+            }
+        }
+        located.push((found, patch));
+    }
+
+    let fallback = located
+        .iter()
+        .find_map(|(file, _)| file.clone())
+        .unwrap_or_else(|| files[0].to_owned());
+
+    let mut grouped: Vec<(String, Vec<Patch>)> = Vec::new();
+    for (file, patch) in located {
+        let file = file.unwrap_or_else(|| fallback.to_owned());
+        info!(
+            "\tlocated {} in {}",
+            patch.pattern.as_deref().unwrap_or("patch"),
+            file
+        );
+
+        match grouped.iter_mut().find(|(f, _)| *f == file) {
+            Some((_, patches)) => patches.push(patch.clone()),
+            None => grouped.push((file, vec![patch.clone()])),
+        }
+    }
+    Ok(grouped)
+}
+
+// patch_file patches a single file matched by a manifest into fsb
+fn patch_file(
+    fsb: &mut FsBuffer,
+    manifest: &Manifest,
+    file: &str,
+    collected: &mut Collected,
+) -> Result<()> {
+    // load code from fsb (loads from file if this is the first occurrence)
+    let code = fsb.load(file.to_owned())?;
+
+    // try patching, as if the manifest only targeted this file
+    let manifest = Manifest {
+        file: ManifestFile::Path(file.to_owned()),
+        ..manifest.clone()
+    };
+    let result = try_patch(code, &manifest)?;
+
+    // update code (with __replaced__ modifications)
+    fsb.update(file, &result.code);
+
+    // update imports
+    collected
+        .imports
+        .entry(file.to_owned())
+        .or_default()
+        .extend(result.imports);
+
+    // update patches; a manifest made of in-place edits only has none
+    if !result.patches.is_empty() {
+        collected
+            .patches
+            .entry(file.to_owned())
+            .or_default()
+            .push((manifest.id(), result.patches.join("\n")));
+    }
+
+    // update symbols
+    collected
+        .symbols
+        .entry(file.to_owned())
+        .or_default()
+        .extend(result.symbols);
+
+    if manifest.prune_imports.unwrap_or(false) {
+        collected.prune_imports.insert(file.to_owned());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, locate, patch, run, Output};
+    use crate::fs_buffer::FsBuffer;
+    use crate::manifest::Manifest;
+    use crate::modules::Modules;
+    use crate::state;
+    use crate::Error;
+
+    const PRISTINE: &str = "package pkg\n\nfunc Other() int {\n\treturn 1\n}\n\nfunc Keep() {\n\tprintln(\"keep\")\n}\n";
+
+    #[test]
+    fn test_apply_twice_and_revert() {
+        let root = std::env::temp_dir().join(format!("govld-run-{}", std::process::id()));
+        let dir = root.join("vendor");
+        let state_dir = root.join(".govld");
+        std::fs::create_dir_all(dir.join("pkg")).unwrap();
+        std::fs::write(dir.join("pkg/a.go"), PRISTINE).unwrap();
+
+        let rename = "file: pkg/a.go\npatch:\n  - pattern: function_declaration\n    patch_type: rename\n    name: Other\n";
+        let insert = "  - pattern: function_declaration\n    patch_type: insert_at_start\n    code: |\n      func Keep() {\n          println(\"start\")\n      }\n";
+        std::fs::write(root.join("rename.yaml"), rename).unwrap();
+        std::fs::write(root.join("both.yaml"), format!("{}{}", rename, insert)).unwrap();
+
+        let modules = Modules::detect(&root, &dir, &root.join(".govld_modules")).unwrap();
+        for manifest in ["rename.yaml", "both.yaml"] {
+            let output = Output::InPlace(state_dir.clone());
+            run(&root, &modules, output, vec![manifest.to_owned()]).unwrap();
         }
+        let patched = std::fs::read_to_string(dir.join("pkg/a.go")).unwrap();
+        assert!(patched.contains("Other__replaced_by_function_decl"));
+        assert!(patched.contains("println(\"start\")"));
+
+        state::revert(&state_dir, &dir).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("pkg/a.go")).unwrap(),
+            PRISTINE
+        );
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_check() {
+        let root = std::env::temp_dir().join(format!("govld-check-{}", std::process::id()));
+        let dir = root.join("vendor");
+        std::fs::create_dir_all(dir.join("pkg")).unwrap();
+        std::fs::write(dir.join("pkg/a.go"), PRISTINE).unwrap();
+        std::fs::write(
+            root.join("m.yaml"),
+            "file: pkg/a.go\npatch:\n  - code: func Other() int { return 2 }\n",
+        )
+        .unwrap();
+
+        let modules = Modules::detect(&root, &dir, &root.join(".govld_modules")).unwrap();
+        let manifests = || vec!["m.yaml".to_owned()];
+
+        // out of date, along with the symbols to patch
+        assert!(!check(&root, &modules, manifests()).unwrap());
+        let fsb = &mut FsBuffer::new(&dir);
+        let symbols = patch(&root, &modules, fsb, false, manifests()).unwrap();
+        assert_eq!(symbols["pkg/a.go"], vec!["Other".to_owned()]);
+        assert_eq!(fsb.modified(), vec!["pkg/a.go"]);
+
+        let output = Output::InPlace(root.join(".govld"));
+        run(&root, &modules, output, manifests()).unwrap();
+        assert!(check(&root, &modules, manifests()).unwrap());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_apply_separately() {
+        let root = std::env::temp_dir().join(format!("govld-separately-{}", std::process::id()));
+        let dir = root.join("vendor");
+        std::fs::create_dir_all(dir.join("pkg")).unwrap();
+        std::fs::write(dir.join("pkg/a.go"), PRISTINE).unwrap();
+        std::fs::write(
+            root.join("a.yaml"),
+            "file: pkg/a.go\npatch:\n  - code: func Other() int { return 2 }\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("b.yaml"),
+            "file: pkg/a.go\npatch:\n  - code: func Added() {}\n",
+        )
+        .unwrap();
+
+        let modules = Modules::detect(&root, &dir, &root.join(".govld_modules")).unwrap();
+        let apply = |manifest: &str| {
+            let output = Output::InPlace(root.join(".govld"));
+            run(&root, &modules, output, vec![manifest.to_owned()]).unwrap();
+            std::fs::read_to_string(dir.join("pkg/a.go")).unwrap()
+        };
+
+        // each run keeps the blocks of the others
+        apply("a.yaml");
+        let patched = apply("b.yaml");
+        assert!(patched.contains("func Other__replaced_by_function_decl() int"));
+        assert!(patched.contains("func Other() int { return 2 }"));
+        assert!(patched.contains("func Added() {}"));
+
+        assert_eq!(apply("a.yaml"), patched);
+        let manifests = vec!["b.yaml".to_owned(), "a.yaml".to_owned()];
+        assert!(check(&root, &modules, manifests).unwrap());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_min_matches() {
+        let root = std::env::temp_dir().join(format!("govld-matches-{}", std::process::id()));
+        let dir = root.join("vendor");
+        std::fs::create_dir_all(dir.join("pkg")).unwrap();
+        std::fs::write(dir.join("pkg/a.go"), PRISTINE).unwrap();
+        std::fs::write(dir.join("pkg/b.go"), "package pkg\n").unwrap();
+        std::fs::write(
+            root.join("three.yaml"),
+            "file: pkg/*.go\nmin_matches: 3\npatch:\n  - code: func Added() {}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("optional.yaml"),
+            "file: pkg/missing_*.go\noptional: true\npatch:\n  - code: func Added() {}\n",
+        )
+        .unwrap();
+
+        let modules = Modules::detect(&root, &dir, &root.join(".govld_modules")).unwrap();
+        let fsb = &mut FsBuffer::new(&dir);
+        let patched = patch(&root, &modules, fsb, false, vec!["three.yaml".to_owned()]);
+        assert!(matches!(
+            patched,
+            Err(Error::TooFewMatches {
+                matches: 2,
+                min_matches: 3,
+                ..
+            })
+        ));
+
+        // an optional manifest matching nothing is skipped
+        let fsb = &mut FsBuffer::new(&dir);
+        let symbols = patch(
+            &root,
+            &modules,
+            fsb,
+            false,
+            vec!["optional.yaml".to_owned()],
+        );
+        assert!(symbols.unwrap().is_empty());
+        assert!(fsb.modified().is_empty());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_check_without_vendoring() {
+        let root = std::env::temp_dir().join(format!("govld-cache-{}", std::process::id()));
+        let cache = root.join("cache");
+        std::fs::create_dir_all(cache.join("example.com/lib@v1.0.0")).unwrap();
+        std::fs::write(cache.join("example.com/lib@v1.0.0/lib.go"), PRISTINE).unwrap();
+        std::fs::write(
+            root.join("go.mod"),
+            "module example.com/app\n\ngo 1.21\n\nrequire example.com/lib v1.0.0\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("m.yaml"),
+            "file: example.com/lib/lib.go\npatch:\n  - code: func Other() int { return 2 }\n",
+        )
+        .unwrap();
+
+        // the only test with a go.mod, hence the only one reading GOMODCACHE
+        std::env::set_var("GOMODCACHE", &cache);
+        let modules_dir = root.join(".govld_modules");
+        let modules = Modules::detect(&root, &root.join("vendor"), &modules_dir).unwrap();
+        let manifests = || vec!["m.yaml".to_owned()];
+
+        // checking reads the module cache, copying nothing
+        assert!(!check(&root, &modules, manifests()).unwrap());
+        assert!(!modules_dir.exists());
+
+        // ...and the patched copy, once there is one
+        let output = Output::InPlace(root.join(".govld"));
+        run(&root, &modules, output, manifests()).unwrap();
+        assert!(check(&root, &modules, manifests()).unwrap());
+        assert_eq!(
+            std::fs::read_to_string(cache.join("example.com/lib@v1.0.0/lib.go")).unwrap(),
+            PRISTINE
+        );
+
+        std::fs::remove_dir_all(root).unwrap();
     }
 
-    // actually write to file
-    fsb.flush();
+    #[test]
+    fn test_locate() {
+        let root = std::env::temp_dir().join(format!("govld-locate-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("pkg")).unwrap();
+        std::fs::write(root.join("pkg/a.go"), "package pkg\n\nfunc A() {}\n").unwrap();
+        std::fs::write(root.join("pkg/b.go"), "package pkg\n\nfunc B() {}\n").unwrap();
+
+        let manifest: Manifest = serde_yaml::from_str(
+            "package: pkg\npatch:\n  - code: func New() {}\n  - code: func B() { b() }\n",
+        )
+        .unwrap();
+        let fsb = &mut FsBuffer::new(&root);
+        let files = vec!["pkg/a.go".to_owned(), "pkg/b.go".to_owned()];
+
+        // a symbol no file declares goes along with the others
+        let located = locate(fsb, &manifest, &files).unwrap();
+        assert_eq!(located.len(), 1);
+        assert_eq!(located[0].0, "pkg/b.go");
+        assert_eq!(located[0].1, manifest.patch);
+
+        // ...or to the first file, if there are no others
+        let manifest = Manifest {
+            patch: manifest.patch[..1].to_vec(),
+            ..manifest
+        };
+        let located = locate(fsb, &manifest, &files).unwrap();
+        assert_eq!(located[0].0, "pkg/a.go");
+
+        assert!(locate(fsb, &manifest, &[]).unwrap().is_empty());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
 }
@@ -0,0 +1,138 @@
+use crate::fs_buffer::{hash, BEGIN_MARKER};
+use crate::{Error, Result};
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+
+// WRITTEN_SUFFIX names the file next to each record holding the hash of the content
+// govld last wrote, telling whether the file has changed since
+const WRITTEN_SUFFIX: &str = ".govld_written";
+
+/// record stores the pre-patch content of a file under the state directory,
+/// so that it can be restored later on by `revert`, along with the hash of the
+/// patched content about to be written.
+///
+/// an existing record is kept as long as the file still holds what govld last wrote:
+/// it was taken before the first run that patched the file, and later runs only ever
+/// see content patched already, with or without govld markers. a file changed since,
+/// e.g. by re-vendoring, is recorded anew.
+pub fn record(state_dir: &Path, path: &str, original: &str, patched: &str) -> Result<()> {
+    let record_path = state_dir.join(path);
+    let written_path = state_dir.join(format!("{}{}", path, WRITTEN_SUFFIX));
+
+    let written = std::fs::read_to_string(&written_path).ok();
+    let unchanged = record_path.exists() && written.as_deref() == Some(hash(original).as_str());
+    if !unchanged && original.contains(BEGIN_MARKER) {
+        warn!("no pristine record for already patched file: {}", path);
+        return Ok(());
+    }
+
+    if let Some(parent) = record_path.parent() {
+        std::fs::create_dir_all(parent).map_err(Error::io(parent))?;
+    }
+
+    if !unchanged {
+        if record_path.exists() {
+            info!("{} changed since patched; recording it anew", path);
+        }
+        std::fs::write(&record_path, original).map_err(Error::io(&record_path))?;
+    }
+    std::fs::write(&written_path, hash(patched)).map_err(Error::io(written_path))
+}
+
+/// revert restores every recorded file into dir and clears its record.
+/// returns the list of restored files.
+pub fn revert(state_dir: &Path, dir: &Path) -> Result<Vec<String>> {
+    let mut restored = records(state_dir)
+        .into_iter()
+        .map(|record_path| {
+            let path = record_path
+                .strip_prefix(state_dir)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_owned();
+
+            let original =
+                std::fs::read_to_string(&record_path).map_err(Error::io(&record_path))?;
+            std::fs::write(dir.join(&path), original).map_err(Error::io(dir.join(&path)))?;
+            std::fs::remove_file(&record_path).map_err(Error::io(&record_path))?;
+
+            let written_path = state_dir.join(format!("{}{}", path, WRITTEN_SUFFIX));
+            if written_path.exists() {
+                std::fs::remove_file(&written_path).map_err(Error::io(&written_path))?;
+            }
+
+            info!("reverted {}", &path);
+            Ok(path)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    restored.sort();
+    Ok(restored)
+}
+
+// records walks the state directory recursively
+fn records(state_dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(state_dir) else {
+        return vec![];
+    };
+
+    entries
+        .flatten()
+        .flat_map(|entry| {
+            let path = entry.path();
+            match path.is_dir() {
+                true => records(&path),
+                false if path.to_string_lossy().ends_with(WRITTEN_SUFFIX) => vec![],
+                false => vec![path],
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{record, revert};
+
+    #[test]
+    fn test_record_and_revert() {
+        let root = std::env::temp_dir().join(format!("govld-state-{}", std::process::id()));
+        let state_dir = root.join(".govld");
+        let dir = root.join("vendor");
+        std::fs::create_dir_all(dir.join("pkg")).unwrap();
+
+        let patched = "package pkg\n// govld:begin 0\n";
+        record(&state_dir, "pkg/a.go", "package pkg\n", patched).unwrap();
+        std::fs::write(dir.join("pkg/a.go"), patched).unwrap();
+
+        // records of pristine content must survive subsequent runs,
+        // including those of in-place patches leaving no marker behind
+        record(&state_dir, "pkg/a.go", patched, "package pkg_renamed\n").unwrap();
+        record(&state_dir, "pkg/a.go", "package pkg_renamed\n", patched).unwrap();
+
+        assert_eq!(
+            revert(&state_dir, &dir).unwrap(),
+            vec!["pkg/a.go".to_owned()]
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("pkg/a.go")).unwrap(),
+            "package pkg\n"
+        );
+        assert!(revert(&state_dir, &dir).unwrap().is_empty());
+
+        // ...but not re-vendoring, replacing what was patched with another version
+        record(&state_dir, "pkg/a.go", "package pkg\n", patched).unwrap();
+        record(&state_dir, "pkg/a.go", "package pkg // v2\n", patched).unwrap();
+        revert(&state_dir, &dir).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("pkg/a.go")).unwrap(),
+            "package pkg // v2\n"
+        );
+        assert!(std::fs::read_dir(state_dir.join("pkg"))
+            .unwrap()
+            .next()
+            .is_none());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}